@@ -0,0 +1,64 @@
+use atoma::{
+    entity::{
+        enemy::Enemy, EntityManager, Message, MessageDispatcher, Telegram, ENTITY_MANAGER_ID,
+        PLAYER_ID,
+    },
+    graphics::sprite::{AnimatedSprite, ENEMIES},
+    level::{template::TemplateRegistry, FloorConfig, Level},
+};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+// Bullets and a fixed number of enemies spread over an arena far larger than a room
+const ARENA_SIZE: f32 = 1024.;
+const ENEMIES_COUNT: u32 = 100;
+const BULLETS: [u32; 5] = [500, 1000, 2000, 4000, 8000];
+
+fn populate(bullets: u32) -> (EntityManager, MessageDispatcher) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut manager = EntityManager::new();
+    let mut dispatcher = MessageDispatcher::new();
+    for _ in 0..ENEMIES_COUNT {
+        let pos = (rng.gen_range(0., ARENA_SIZE), rng.gen_range(0., ARENA_SIZE)).into();
+        let sprite = AnimatedSprite::new(ENEMIES.to_vec(), vec![30, 45, 55, 60, 65]);
+        manager.add_entity(Box::new(Enemy::new(pos, 0.5, sprite)));
+    }
+    for _ in 0..bullets {
+        let pos = (rng.gen_range(0., ARENA_SIZE), rng.gen_range(0., ARENA_SIZE)).into();
+        let telegram = Telegram {
+            sender: PLAYER_ID,
+            receiver: ENTITY_MANAGER_ID,
+            message: Message::SpawnEntity(pos, (1., 0.).into(), 0.),
+        };
+        manager.handle_message(telegram, &mut dispatcher);
+    }
+    (manager, dispatcher)
+}
+
+fn collision_pass(c: &mut Criterion) {
+    let templates = TemplateRegistry::load(&TemplateRegistry::default_dir()).unwrap();
+    let level = Level::new(&templates, FloorConfig::for_depth(1), 0).unwrap();
+    let mut group = c.benchmark_group("collision pass");
+    for &bullets in BULLETS.iter() {
+        // Hits remove bullets and kill enemies, so every pass starts from a fresh population
+        group.bench_with_input(
+            BenchmarkId::from_parameter(bullets),
+            &bullets,
+            |b, &bullets| {
+                b.iter_batched(
+                    || populate(bullets),
+                    |(mut manager, mut dispatcher)| {
+                        manager.check_collisions(level.current_room(), &mut dispatcher);
+                        dispatcher.dispatch_messages(&mut manager);
+                        manager
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, collision_pass);
+criterion_main!(benches);
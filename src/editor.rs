@@ -0,0 +1,176 @@
+use crate::graphics::{
+    screen::Screen,
+    sprite::{SPRITE_SIZE_SHIFT_VALUE, SPRITE_SIZE_U32},
+};
+use crate::input::{keyboard::KeyBoard, Key, MouseButton};
+use crate::level::{
+    template::{Cell, RoomTemplate, TemplateRegistry},
+    tile::{Hazard, Prop},
+};
+use cgmath::Vector2;
+use std::collections::HashSet;
+
+// Paints the cells of a room template with the mouse. The left button paints the brush,
+// the right one clears back to floor, the brush keys cycle through `BRUSHES`.
+static EXIT_KEY: &Key = &Key::F1;
+static SAVE_KEY: &Key = &Key::Return;
+static NEXT_BRUSH_KEY: &Key = &Key::E;
+static PREV_BRUSH_KEY: &Key = &Key::Q;
+const SCROLL_SPEED: i32 = 4;
+const BRUSHES: [Cell; 14] = [
+    Cell::Wall,
+    Cell::DoorCandidate,
+    Cell::Grass,
+    Cell::SpawnPoint,
+    Cell::EnemySpawn,
+    Cell::Hazard(Hazard::Spikes),
+    Cell::Hazard(Hazard::Pit),
+    Cell::Hazard(Hazard::Water),
+    Cell::Hazard(Hazard::Mud),
+    Cell::Hazard(Hazard::Brush),
+    Cell::Prop(Prop::Rock),
+    Cell::Prop(Prop::Pot),
+    Cell::Prop(Prop::Crate),
+    Cell::Empty,
+];
+const SWATCH_POS: Vector2<u32> = Vector2::new(2, 2);
+const SWATCH_SIZE: Vector2<u32> = Vector2::new(10, 10);
+const CURSOR_COLOUR: image::Rgba<u8> = image::Rgba {
+    data: [255, 255, 255, 255],
+};
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum EditorCommand {
+    // Cells of the template changed
+    Painted,
+    Save,
+    Exit,
+}
+
+pub struct Editor {
+    template: RoomTemplate,
+    brush: usize,
+    // Keys held on the previous tick, actions only trigger when a key goes down
+    held: HashSet<Key>,
+}
+
+impl Editor {
+    pub fn new(template: RoomTemplate, keyboard: &KeyBoard) -> Editor {
+        Editor {
+            template,
+            brush: 0,
+            held: keyboard.keys.clone(),
+        }
+    }
+
+    pub fn template(&self) -> &RoomTemplate {
+        &self.template
+    }
+
+    fn pressed(&self, keyboard: &KeyBoard, key: &Key) -> bool {
+        keyboard.contains_key(key) && !self.held.contains(key)
+    }
+
+    pub fn update(&mut self, keyboard: &KeyBoard, cursor: Vector2<i32>) -> Option<EditorCommand> {
+        let command = if self.pressed(keyboard, EXIT_KEY) {
+            Some(EditorCommand::Exit)
+        } else if self.pressed(keyboard, SAVE_KEY) {
+            Some(EditorCommand::Save)
+        } else {
+            if self.pressed(keyboard, NEXT_BRUSH_KEY) {
+                self.brush = (self.brush + 1) % BRUSHES.len();
+            }
+            if self.pressed(keyboard, PREV_BRUSH_KEY) {
+                self.brush = (self.brush + BRUSHES.len() - 1) % BRUSHES.len();
+            }
+            let cell = if keyboard.is_pressed(MouseButton::Left) {
+                Some(BRUSHES[self.brush])
+            } else if keyboard.is_pressed(MouseButton::Right) {
+                Some(Cell::Grass)
+            } else {
+                None
+            };
+            cell.and_then(|cell| self.paint(cursor, cell))
+        };
+        self.held = keyboard.keys.clone();
+        command
+    }
+
+    fn paint(&mut self, pos: Vector2<i32>, cell: Cell) -> Option<EditorCommand> {
+        let Vector2 { x: width, y: height } = self.template.dimensions;
+        if pos.x < 0 || pos.y < 0 || pos.x as u32 >= width || pos.y as u32 >= height {
+            return None;
+        }
+        let (x, y) = (pos.x as u32, pos.y as u32);
+        if self.template.cell(x, y) == cell {
+            return None;
+        }
+        self.template.set_cell(x, y, cell);
+        Some(EditorCommand::Painted)
+    }
+
+    // Camera movement in pixels from the arrow keys
+    pub fn scroll(&self, keyboard: &KeyBoard) -> Vector2<i32> {
+        let mut scroll = Vector2::new(0, 0);
+        if keyboard.left {
+            scroll.x -= SCROLL_SPEED;
+        }
+        if keyboard.right {
+            scroll.x += SCROLL_SPEED;
+        }
+        if keyboard.up {
+            scroll.y -= SCROLL_SPEED;
+        }
+        if keyboard.down {
+            scroll.y += SCROLL_SPEED;
+        }
+        scroll
+    }
+
+    // Drawn over the room: markers on the cells that look like plain floor or wall once
+    // built, the tile under the cursor and the palette colour of the brush
+    pub fn render(
+        &self,
+        screen: &mut Screen,
+        offset: Vector2<i32>,
+        cursor: Vector2<i32>,
+        templates: &TemplateRegistry,
+    ) {
+        let tile_size = Vector2::new(SPRITE_SIZE_U32, SPRITE_SIZE_U32);
+        let Vector2 { x: width, y: height } = self.template.dimensions;
+        for y in 0..height {
+            for x in 0..width {
+                let cell = self.template.cell(x, y);
+                if !matches!(
+                    cell,
+                    Cell::SpawnPoint | Cell::EnemySpawn | Cell::DoorCandidate
+                ) {
+                    continue;
+                }
+                if let Some(colour) = templates.colour(cell) {
+                    let pos = Vector2::new(x as i32, y as i32).map(|c| c << SPRITE_SIZE_SHIFT_VALUE);
+                    for inset in 3..5 {
+                        screen.outline_rect(
+                            pos - offset + Vector2::new(inset, inset),
+                            tile_size - Vector2::new(inset as u32, inset as u32) * 2,
+                            image::Rgba(colour),
+                        );
+                    }
+                }
+            }
+        }
+        screen.outline_rect(
+            cursor.map(|c| c << SPRITE_SIZE_SHIFT_VALUE) - offset,
+            tile_size,
+            CURSOR_COLOUR,
+        );
+        if let Some(colour) = templates.colour(BRUSHES[self.brush]) {
+            screen.fill_rect(SWATCH_POS, SWATCH_SIZE, image::Rgba(colour));
+        }
+        screen.outline_rect(
+            SWATCH_POS.cast().unwrap() - Vector2::new(1, 1),
+            SWATCH_SIZE + Vector2::new(2, 2),
+            CURSOR_COLOUR,
+        );
+    }
+}
@@ -1,40 +1,197 @@
-use crate::entity::{Collider, CollisionKind, Direction, Entity, EntityId, MessageDispatcher, INVALID_ID};
-use crate::graphics::sprite::AnimatedSprite;
-use crate::level::room::Room;
-use cgmath::Vector2;
+use crate::entity::{
+    Collider, CollisionKind, Entity, EntityId, Message, MessageDispatcher, Telegram,
+    INVALID_ID, PLAYER_ID,
+    health::Health,
+    moving_component::{Force, MovingComponent},
+    state::{State, StateMachine},
+    steering::{self, PathFollower, Wander},
+};
+use crate::graphics::{screen::Screen, sprite::AnimatedSprite};
+use crate::level::{navigation::Movement, room::Room};
+use cgmath::{InnerSpace, Vector2};
 use image::{RgbaImage, SubImage};
 
+const ENEMY_HIT_POINTS: u32 = 3;
+const FLEE_HIT_POINTS: u32 = 1;
+const CONTACT_DAMAGE: u32 = 1;
+const SIGHT_RADIUS: f32 = 80.;
+const LOSE_SIGHT_RADIUS: f32 = 120.;
+const ARRIVE_RADIUS: f32 = 24.;
+const WAYPOINT_RADIUS: f32 = 6.;
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum EnemyState {
+    Wander,
+    Seek,
+    Flee,
+    Arrive,
+}
+
+impl State<Enemy> for EnemyState {
+    fn enter(&self, _owner: &mut Enemy, _dispatcher: &mut MessageDispatcher) {}
+    fn execute(&self, owner: &mut Enemy, _dispatcher: &mut MessageDispatcher) {
+        let position = owner.center();
+        let velocity = owner.moving.velocity();
+        let max_speed = owner.moving.max_speed();
+        let target = match owner.target {
+            Some(target) => target,
+            None => {
+                owner.steering = owner.wander.force(velocity, max_speed);
+                return;
+            }
+        };
+        let distance = (target - position).magnitude();
+        let fleeing = owner.health.hit_points() <= FLEE_HIT_POINTS;
+        match *self {
+            EnemyState::Wander => {
+                owner.steering = owner.wander.force(velocity, max_speed);
+                if distance < SIGHT_RADIUS {
+                    owner.next_state = Some(EnemyState::Seek);
+                }
+            }
+            EnemyState::Seek => {
+                // Walls are avoided by following the path to the target's tile
+                owner.steering = owner
+                    .path
+                    .force(position, velocity, max_speed)
+                    .unwrap_or_else(|| steering::seek(position, velocity, target, max_speed));
+                if fleeing {
+                    owner.next_state = Some(EnemyState::Flee);
+                } else if distance > LOSE_SIGHT_RADIUS {
+                    owner.next_state = Some(EnemyState::Wander);
+                } else if distance < ARRIVE_RADIUS {
+                    owner.next_state = Some(EnemyState::Arrive);
+                }
+            }
+            EnemyState::Arrive => {
+                owner.steering =
+                    steering::arrive(position, velocity, target, max_speed, ARRIVE_RADIUS);
+                if fleeing {
+                    owner.next_state = Some(EnemyState::Flee);
+                } else if distance > ARRIVE_RADIUS * 1.5 {
+                    owner.next_state = Some(EnemyState::Seek);
+                }
+            }
+            EnemyState::Flee => {
+                owner.steering = steering::flee(position, velocity, target, max_speed);
+                if distance > LOSE_SIGHT_RADIUS {
+                    owner.next_state = Some(EnemyState::Wander);
+                }
+            }
+        }
+    }
+    fn exit(&self, _owner: &mut Enemy, _dispatcher: &mut MessageDispatcher) {}
+}
+
 pub struct Enemy {
-    position: Vector2<f32>,
-    _speed: f32,
-    _direction: Direction,
+    moving: MovingComponent,
     removed: bool,
     sprite: AnimatedSprite,
-    _collides: bool,
-    _flipped: bool,
+    health: Health,
     id: EntityId,
+    target: Option<Vector2<f32>>,
+    steering: Vector2<f32>,
+    wander: Wander,
+    path: PathFollower,
+    states: Option<StateMachine<Self, EnemyState>>,
+    next_state: Option<EnemyState>,
 }
 
 impl Enemy {
-    pub fn new(position: Vector2<f32>, _speed: f32, sprite: AnimatedSprite) -> Enemy {
+    pub fn new(position: Vector2<f32>, max_speed: f32, sprite: AnimatedSprite) -> Enemy {
+        let mut moving = MovingComponent::new(
+            1.0, // mass
+            max_speed,
+            0.05, // max_force
+        );
+        moving.set_pos(position);
         Enemy {
-            position,
-            _speed,
-            _direction: Direction::RIGHT,
+            moving,
             removed: false,
             sprite,
-            _collides: false,
-            _flipped: false,
+            health: Health::new(ENEMY_HIT_POINTS),
             id: INVALID_ID,
+            target: None,
+            steering: (0., 0.).into(),
+            wander: Wander::new(0),
+            path: PathFollower::new(WAYPOINT_RADIUS),
+            states: Some(StateMachine::new(None, EnemyState::Wander)),
+            next_state: None,
+        }
+    }
+
+    // Enemies don't walk into pits
+    fn collision(&self, room: &Room) -> bool {
+        self.collider()
+            .unwrap()
+            .room_tiles(room)
+            .iter()
+            .any(|tile| tile.solid || tile.pit)
+    }
+
+    fn center(&self) -> Vector2<f32> {
+        self.collider().unwrap().center()
+    }
+
+    // Only searches again when the target moved to another tile
+    fn update_path(&mut self, room: &Room) {
+        let goal = match self.target {
+            Some(target) => room.tile_pos(target),
+            None => return self.path.set_path(None),
+        };
+        if self.path.goal() != Some(goal) {
+            let start = room.tile_pos(self.center());
+            let movement = Movement {
+                diagonal: true,
+                cut_corners: false,
+            };
+            self.path.set_path(room.find_path(start, goal, movement));
         }
     }
 }
 
 impl Entity for Enemy {
-    fn update(&mut self, _room: &Room, _dispatcher: &mut MessageDispatcher) {
+    fn move_entity(&mut self, forces: &[Force], room: &Room) -> bool {
+        let old_pos = self.moving.pos();
+        let speed = room.tile_at(self.center()).speed;
+        let moved = self.moving.update(forces, false);
+        let new_pos = old_pos + (self.moving.pos() - old_pos) * speed;
+        let mut velocity = self.moving.velocity();
+        // Blocked axes lose their velocity so the enemy slides along walls
+        self.moving.set_pos((new_pos.x, old_pos.y).into());
+        if self.collision(room) {
+            self.moving.set_pos(old_pos);
+            velocity.x = 0.;
+        }
+        let old_pos = self.moving.pos();
+        self.moving.set_pos((old_pos.x, new_pos.y).into());
+        if self.collision(room) {
+            self.moving.set_pos(old_pos);
+            velocity.y = 0.;
+        }
+        self.moving.set_velocity(velocity);
+        moved
+    }
+
+    fn update(&mut self, room: &Room, dispatcher: &mut MessageDispatcher) {
+        self.health.update();
+        self.update_path(room);
+        let mut states = self.states.take().unwrap();
+        states.update(self, dispatcher);
+        if let Some(state) = self.next_state.take() {
+            states.change_state(self, state, dispatcher);
+        }
+        self.states = Some(states);
+        self.moving.set_thrust(Force::new(self.steering, 2));
+        self.move_entity(&[], room);
         self.sprite.update()
     }
 
+    fn render(&self, screen: &mut Screen, offset: Vector2<f32>) {
+        let flip = self.moving.velocity().x < 0.;
+        self.render_impl(screen, offset, flip);
+    }
+
     fn sprite_view(&self) -> Option<SubImage<&RgbaImage>> {
         Some(self.sprite.view())
     }
@@ -47,23 +204,74 @@ impl Entity for Enemy {
         self.removed
     }
 
+    fn set_pos(&mut self, pos: Vector2<f32>) {
+        self.moving.set_pos(pos);
+    }
+
     fn relative_pos(&self, offset: Vector2<f32>) -> Vector2<i32> {
-        (self.position - offset).cast().unwrap()
+        (self.moving.pos() - offset).cast().unwrap()
     }
 
     fn absolute_pos(&self) -> Vector2<i32> {
-        self.position.cast::<i32>().unwrap()
+        self.moving.pos().cast::<i32>().unwrap()
     }
 
     fn collider(&self) -> Option<Collider> {
         Some(Collider::new(
-            self.position + Vector2::new(1., 6.0),
+            self.moving.pos() + Vector2::new(1., 6.0),
             (14.0, 10.0).into(),
             CollisionKind::Hostile,
         ))
     }
 
+    fn apply_force(&mut self, force: Force) {
+        self.moving.add_force(force);
+    }
+
+    fn set_target(&mut self, target: Option<Vector2<f32>>) {
+        self.target = target;
+    }
+
+    fn is_persistent(&self) -> bool {
+        true
+    }
+
     fn id(&self) -> EntityId {
         self.id
     }
+
+    fn set_id(&mut self, id: EntityId) {
+        self.id = id;
+        // Seeding from the id keeps wandering reproducible for a given level
+        self.wander = Wander::new(id as u64);
+    }
+
+    fn handle_message(&mut self, message: Telegram, dispatcher: &mut MessageDispatcher) {
+        let Telegram {
+            sender,
+            receiver: _,
+            message,
+        } = message;
+        match message {
+            Message::Collides if sender == PLAYER_ID => {
+                self.send_message(Message::Damage(CONTACT_DAMAGE), PLAYER_ID, dispatcher);
+            }
+            Message::Damage(amount) => {
+                self.health.take_damage(amount);
+                if self.health.is_dead() {
+                    self.remove();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn send_message(
+        &self,
+        message: Message,
+        receiver: EntityId,
+        dispatcher: &mut MessageDispatcher,
+    ) {
+        dispatcher.queue_message(self.id(), receiver, message);
+    }
 }
@@ -0,0 +1,57 @@
+pub struct Health {
+    hit_points: u32,
+    max_hit_points: u32,
+    invulnerable: u32,
+}
+
+impl Health {
+    pub fn new(max_hit_points: u32) -> Self {
+        Health {
+            hit_points: max_hit_points,
+            max_hit_points,
+            invulnerable: 0,
+        }
+    }
+
+    pub fn hit_points(&self) -> u32 {
+        self.hit_points
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.hit_points == 0
+    }
+
+    pub fn is_invulnerable(&self) -> bool {
+        self.invulnerable > 0
+    }
+
+    pub fn invulnerable_frames(&self) -> u32 {
+        self.invulnerable
+    }
+
+    pub fn set_invulnerable(&mut self, frames: u32) {
+        self.invulnerable = frames;
+    }
+
+    // Returns false if the damage was ignored because of invulnerability or death
+    pub fn take_damage(&mut self, amount: u32) -> bool {
+        if self.is_invulnerable() || self.is_dead() {
+            return false;
+        }
+        self.hit_points = self.hit_points.saturating_sub(amount);
+        true
+    }
+
+    // Returns false if there was nothing to heal
+    pub fn heal(&mut self, amount: u32) -> bool {
+        if self.is_dead() || self.hit_points == self.max_hit_points {
+            return false;
+        }
+        self.hit_points = (self.hit_points + amount).min(self.max_hit_points);
+        true
+    }
+
+    pub fn update(&mut self) {
+        self.invulnerable = self.invulnerable.saturating_sub(1);
+    }
+}
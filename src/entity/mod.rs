@@ -1,22 +1,31 @@
 pub mod enemy;
+mod health;
 mod moving_component;
+pub mod pickup;
 pub mod player;
 mod projectile;
+mod spatial_grid;
 mod state;
+mod steering;
+pub mod trapdoor;
 
 use bitflags;
 use crate::graphics::{
     screen::Screen,
-    sprite::{SPRITE_SIZE_F32, SPRITE_SIZE_U32}
+    sprite::{Sprite, DOOR, LOCKED_DOOR, OPEN_DOOR, SPRITE_SIZE_F32, SPRITE_SIZE_U32}
 };
 use projectile::Projectile;
-use crate::level::room::{Room, RoomId};
-use cgmath::Vector2;
+use crate::level::{
+    room::{DoorInfo, Room, RoomId},
+    tile::Tile,
+};
+use cgmath::{InnerSpace, Vector2};
 use image::{Rgba, GenericImageView, RgbaImage, SubImage};
 use moving_component::Force;
+use spatial_grid::SpatialGrid;
 
 use std::boxed::Box;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, VecDeque};
 
 pub type EntityId = u32;
 
@@ -26,11 +35,19 @@ pub const PLAYER_ID: EntityId = 2;
 pub const ENTITY_MANAGER_ID: EntityId = 3;
 const FIRST_FREE_ID: EntityId = 10;
 const EPSILON: Vector2<f32> = Vector2::new(0.005, 0.005);
+const KNOCKBACK_FORCE: f32 = 5.0;
+const KNOCKBACK_FRAMES: i32 = 8;
 
 pub trait Entity {
     fn update(&mut self, room: &Room, dispatcher: &mut MessageDispatcher);
     fn move_entity(&mut self, _forces: &[Force], _room: &Room) -> bool { false }
-    fn reset_pos(&mut self) {}
+    fn push_out(&mut self, _offset: Vector2<f32>, _room: &Room) {}
+    fn apply_force(&mut self, _force: Force) {}
+    fn set_target(&mut self, _target: Option<Vector2<f32>>) {}
+    // Persistent entities are stored in their room when the player leaves it
+    fn is_persistent(&self) -> bool {
+        false
+    }
     fn render(&self, screen: &mut Screen, offset: Vector2<f32>) {
         self.render_impl(screen, offset, false)
     }
@@ -157,6 +174,10 @@ bitflags! {
 pub enum CollisionKind {
     Friendly,
     Hostile,
+    // Reports collisions without pushing entities apart, e.g. doors
+    Trigger,
+    // Pushes friendly entities out like a wall, e.g. locked doors
+    Solid,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -198,47 +219,134 @@ impl Collider {
         self.origin
     }
 
+    pub fn center(&self) -> Vector2<f32> {
+        self.origin + self.dimensions / 2.
+    }
+
+    // Minimum translation vector that moves `self` out of `other`
+    pub fn penetration(&self, other: &Collider) -> Option<Vector2<f32>> {
+        if !self.intersects(other) {
+            return None;
+        }
+        let to_left = self.origin.x + self.dimensions.x - other.origin.x;
+        let to_right = other.origin.x + other.dimensions.x - self.origin.x;
+        let to_top = self.origin.y + self.dimensions.y - other.origin.y;
+        let to_bottom = other.origin.y + other.dimensions.y - self.origin.y;
+        let x = if to_left < to_right { -to_left } else { to_right };
+        let y = if to_top < to_bottom { -to_top } else { to_bottom };
+        if x.abs() < y.abs() {
+            Some((x, 0.).into())
+        } else {
+            Some((0., y).into())
+        }
+    }
+
+    // Tiles under the four corners of the collider
+    pub fn room_tiles(&self, room: &Room) -> [&'static Tile; 4] {
+        let xy = self.origin.cast::<i32>().unwrap();
+        let size_minus_one = self.dimensions.cast::<i32>().unwrap() - Vector2::new(1, 1);
+        let xy0 = xy / SPRITE_SIZE_U32 as i32;
+        let xy1 = (xy + size_minus_one) / SPRITE_SIZE_U32 as i32;
+        [
+            room.get_tile(xy0.x, xy0.y),
+            room.get_tile(xy1.x, xy0.y),
+            room.get_tile(xy0.x, xy1.y),
+            room.get_tile(xy1.x, xy1.y),
+        ]
+    }
+
+    // Tests the four corners of the collider against the solid tiles of the room
+    pub fn collides_with_room(&self, room: &Room) -> bool {
+        self.room_tiles(room).iter().any(|tile| tile.solid)
+    }
+
+    pub fn friendly(&self) -> bool {
+        self.kind == CollisionKind::Friendly
+    }
+
     pub fn hostile(&self) -> bool {
         self.kind == CollisionKind::Hostile
     }
 
+    pub fn solid(&self) -> bool {
+        self.kind == CollisionKind::Solid
+    }
+
     #[cfg(feature = "debug_rect")]
     pub fn relative_pos(&self, offset: Vector2<f32>) -> Vector2<i32> {
         (self.origin - offset).cast().unwrap()
     }
 }
 
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum DoorState {
+    Open,
+    // Shut, but opens as soon as the player walks into it
+    Closed,
+    // Shut until the room is cleared
+    Locked,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Door {
     pub collider: Collider,
     id: EntityId,
     pub room: RoomId,
+    // Grid cell of the other room the door leads into
+    cell: Vector2<i32>,
     pub removed: bool,
+    state: DoorState,
+    // State to switch to once the room is cleared
+    unlocked_state: DoorState,
 }
 
 static DOOR_COLLIDER_DIMS: Vector2<f32> = Vector2::new(1., 1.);
 static DOOR_COLLIDER_OFFSET: Vector2<f32> =
     Vector2::new(SPRITE_SIZE_F32 / 2., SPRITE_SIZE_F32 / 2.);
 
-impl<'a> From<&'a (Vector2<u32>, RoomId)> for Door {
-    fn from(info: &(Vector2<u32>, RoomId)) -> Self {
+impl From<&DoorInfo> for Door {
+    fn from(info: &DoorInfo) -> Self {
         Door {
             collider: Collider::new(
-                (info.0 * SPRITE_SIZE_U32).cast().unwrap() + DOOR_COLLIDER_OFFSET,
+                (info.tile * SPRITE_SIZE_U32).cast().unwrap() + DOOR_COLLIDER_OFFSET,
                 DOOR_COLLIDER_DIMS,
-                CollisionKind::Hostile,
+                CollisionKind::Trigger,
             ),
             id: INVALID_ID,
-            room: info.1,
+            room: info.room,
+            cell: info.cell + info.side.offset(),
             removed: false,
+            state: DoorState::Open,
+            unlocked_state: DoorState::Open,
         }
     }
 }
 
+impl Door {
+    // `state` is what the door shows once it is unlocked, `locked` locks it until the
+    // room is cleared
+    pub fn with_state(mut self, state: DoorState, locked: bool) -> Self {
+        self.unlocked_state = state;
+        self.state = if locked { DoorState::Locked } else { state };
+        self
+    }
+
+    fn tile_origin(&self) -> Vector2<f32> {
+        self.collider.origin() - DOOR_COLLIDER_OFFSET
+    }
+}
+
 impl Entity for Door {
     fn update(&mut self, _room: &Room, _dispatcher: &mut MessageDispatcher) {}
 
-    fn render(&self, _screen: &mut Screen, _offset: Vector2<f32>) {}
+    fn sprite_view(&self) -> Option<SubImage<&RgbaImage>> {
+        let sprite: &Sprite = match self.state {
+            DoorState::Open => &OPEN_DOOR,
+            DoorState::Closed => &DOOR,
+            DoorState::Locked => &LOCKED_DOOR,
+        };
+        Some(sprite.view())
+    }
 
     fn remove(&mut self) {
         self.removed = true;
@@ -249,15 +357,23 @@ impl Entity for Door {
     }
 
     fn relative_pos(&self, offset: Vector2<f32>) -> Vector2<i32> {
-        (self.collider.origin() - offset).cast().unwrap()
+        (self.tile_origin() - offset).cast().unwrap()
     }
 
     fn absolute_pos(&self) -> Vector2<i32> {
         self.collider.origin().cast().unwrap()
     }
 
+    // A locked door blocks the whole tile, otherwise only its middle triggers
     fn collider(&self) -> Option<Collider> {
-        Some(self.collider)
+        match self.state {
+            DoorState::Locked => Some(Collider::new(
+                self.tile_origin(),
+                (SPRITE_SIZE_F32, SPRITE_SIZE_F32).into(),
+                CollisionKind::Solid,
+            )),
+            _ => Some(self.collider),
+        }
     }
 
     fn id(&self) -> EntityId {
@@ -275,10 +391,15 @@ impl Entity for Door {
             message,
         } = message;
         match message {
-            Message::Collides => {
-                if sender == PLAYER_ID {
-                    self.send_message(Message::LoadRoom(self.room), GAME_ID, dispatcher);
+            Message::Collides if sender == PLAYER_ID => match self.state {
+                DoorState::Open => {
+                    self.send_message(Message::LoadRoom(self.room, self.cell), GAME_ID, dispatcher);
                 }
+                DoorState::Closed => self.state = DoorState::Open,
+                DoorState::Locked => {}
+            },
+            Message::RoomCleared if self.state == DoorState::Locked => {
+                self.state = self.unlocked_state;
             }
             _ => {}
         }
@@ -303,21 +424,40 @@ pub struct Telegram {
 
 #[derive(Debug, Copy, Clone)]
 pub enum Message {
-    LoadRoom(RoomId),
+    // Room to enter and the grid cell of its door the player comes through
+    LoadRoom(RoomId, Vector2<i32>),
+    LoadLevel,
     SpawnEntity(Vector2<f32>, Vector2<f32>, f32),
     Collides,
+    Damage(u32),
+    Heal(u32),
+    // Answer to a pickup that its effect was used
+    PickedUp,
+    // Breaks the prop on a tile of the current room
+    DestroyTile(Vector2<i32>),
+    Died,
+    RoomCleared,
 }
 
 pub struct EntityManager {
-    entities: HashMap<EntityId, Box<dyn Entity>>,
+    // Ordered so that updates and messages replay deterministically.
+    entities: BTreeMap<EntityId, Box<dyn Entity>>,
     next_id: EntityId,
+    grid: SpatialGrid,
+}
+
+impl Default for EntityManager {
+    fn default() -> EntityManager {
+        EntityManager::new()
+    }
 }
 
 impl EntityManager {
     pub fn new() -> Self {
         EntityManager {
-            entities: HashMap::with_capacity(1),
+            entities: BTreeMap::new(),
             next_id: FIRST_FREE_ID,
+            grid: SpatialGrid::new(),
         }
     }
 
@@ -347,75 +487,104 @@ impl EntityManager {
         }
     }
 
-    // TODO: this is just a temporary solution
-    pub fn clean_up(&mut self) {
-        self.entities.retain(|&k, _| k == PLAYER_ID);
+    /// Removes every entity except the player and returns the ones that should be
+    /// restored when the player comes back to the room.
+    pub fn take_room_entities(&mut self) -> Vec<Box<dyn Entity>> {
+        let player = self.entities.remove(&PLAYER_ID);
+        let entities = std::mem::take(&mut self.entities);
+        if let Some(player) = player {
+            self.entities.insert(PLAYER_ID, player);
+        }
+        entities
+            .into_values()
+            .filter(|entity| entity.is_persistent() && !entity.is_removed())
+            .collect()
+    }
+
+    // Sends `message` to every entity and to the game
+    pub fn broadcast(&self, sender: EntityId, message: Message, dispatcher: &mut MessageDispatcher) {
+        dispatcher.queue_message(sender, GAME_ID, message);
+        for id in self.entities.keys() {
+            dispatcher.queue_message(sender, *id, message);
+        }
+    }
+
+    pub fn hostile_count(&self) -> usize {
+        self.entities
+            .values()
+            .filter(|entity| entity.collider().map(|c| c.hostile()) == Some(true))
+            .count()
+    }
+
+    // Where the enemies head, nowhere while the player hides in the brush
+    fn target(&self, room: &Room) -> Option<Vector2<f32>> {
+        self.entities
+            .get(&PLAYER_ID)
+            .and_then(|player| player.collider())
+            .map(|collider| collider.center())
+            .filter(|center| !room.tile_at(*center).hides)
     }
 
     pub fn update(&mut self, room: &Room, dispatcher: &mut MessageDispatcher) {
-        self.entities.retain(|_id, entity| {
+        let target = self.target(room);
+        self.entities.retain(|id, entity| {
+            if *id != PLAYER_ID {
+                entity.set_target(target);
+            }
             entity.update(room, dispatcher);
             !entity.is_removed()
         });
     }
 
+    // The player is drawn last, on top of everything it walks over
     pub fn render(&self, screen: &mut Screen, offset: Vector2<f32>) {
-        for entity in self.entities.values() {
+        for (_, entity) in self.entities.iter().filter(|(id, _)| **id != PLAYER_ID) {
             entity.render(screen, offset);
         }
+        if let Some(player) = self.entities.get(&PLAYER_ID) {
+            player.render(screen, offset);
+        }
     }
 
-    pub fn check_collisions(&mut self, dispatcher: &mut MessageDispatcher) {
-        let mut colliding_entites = Vec::new();
-        {
-            let mut player = self.entities.remove(&PLAYER_ID).unwrap();
-            for (id, ref e) in self.entities.iter() {
-                if player.collides_with(&e.collider()) {
-                    dispatcher.queue_message(PLAYER_ID, *id, Message::Collides);
-                    dispatcher.queue_message(*id, PLAYER_ID, Message::Collides);
-                    colliding_entites.push((*id, e.collider().unwrap()));
+    pub fn check_collisions(&mut self, room: &Room, dispatcher: &mut MessageDispatcher) {
+        self.grid.rebuild(
+            self.entities
+                .iter()
+                .filter_map(|(id, e)| e.collider().map(|collider| (*id, collider))),
+        );
+        for (friendly, other) in self.grid.candidate_pairs() {
+            let (friendly_id, _) = *self.grid.collider(friendly);
+            let (other_id, other_collider) = *self.grid.collider(other);
+            let entity = self.entities.get_mut(&friendly_id).unwrap();
+            if !entity.collides_with(&Some(other_collider)) {
+                continue;
+            }
+            dispatcher.queue_message(friendly_id, other_id, Message::Collides);
+            dispatcher.queue_message(other_id, friendly_id, Message::Collides);
+            if !other_collider.hostile() && !other_collider.solid() {
+                continue;
+            }
+            let penetration = entity
+                .collider()
+                .and_then(|collider| collider.penetration(&other_collider));
+            if let Some(offset) = penetration {
+                entity.push_out(offset, room);
+                if other_collider.hostile() && offset.magnitude2() > 0. {
+                    entity.apply_force(Force::new(
+                        offset.normalize() * KNOCKBACK_FORCE,
+                        KNOCKBACK_FRAMES,
+                    ));
                 }
             }
-            self.entities.insert(PLAYER_ID, player);
-        }
-        if !colliding_entites.is_empty() {
-            let player = self.entities.get_mut(&PLAYER_ID).unwrap();
-            player.reset_pos();
         }
-        /*let player_collider = player.collider().unwrap();
-        let epsilon = 0.5;
-        for (id, collider) in colliding_entites {
-            let Vector2 {
-                x: enemy_x,
-                y: enemy_y,
-            } = collider.origin;
-            let x = player_collider.origin.x - enemy_x;
-            let y = player_collider.origin.y - enemy_y;
-            let x_dir = x.signum();
-            let y_dir = y.signum();
-            if x.abs() >= y.abs() {
-                // Picke the collider size of the top left entity
-                let collider_size = if x_dir.is_sign_positive() {
-                    collider.dimensions.x
-                } else {
-                    player_collider.dimensions.x
-                };
-                let dist = collider_size - x.abs() + epsilon;
-                let offset = (x_dir * dist.ceil(), 0.).into();
-                player.offset_pos(offset);
-            } else {
-                let collider_size = if y_dir.is_sign_positive() {
-                    collider.dimensions.y
-                } else {
-                    player_collider.dimensions.y
-                };
-                let dist = collider_size - y.abs() + epsilon;
-                let offset = (0. , y_dir * dist.ceil()).into();
-                player.offset_pos(offset);
-            }
-            dispatcher.queue_message(PLAYER_ID, id, Message::Collides);
-            dispatcher.queue_message(id, PLAYER_ID, Message::Collides);
-        }*/
+    }
+
+    pub fn get_entity(&self, id: &EntityId) -> Option<&dyn Entity> {
+        self.entities.get(id).map(|entity| entity.as_ref())
+    }
+
+    pub fn entity_ids(&self) -> Vec<EntityId> {
+        self.entities.keys().cloned().collect()
     }
 
     pub fn get_entity_mut(&mut self, id: &EntityId) -> &mut Box<dyn Entity> {
@@ -435,6 +604,12 @@ pub struct MessageDispatcher {
     messages: VecDeque<Telegram>,
 }
 
+impl Default for MessageDispatcher {
+    fn default() -> MessageDispatcher {
+        MessageDispatcher::new()
+    }
+}
+
 impl MessageDispatcher {
     pub fn new() -> Self {
         MessageDispatcher {
@@ -453,8 +628,10 @@ impl MessageDispatcher {
     }
 
     fn discharge(&mut self, manager: &mut EntityManager, message: Telegram) {
-        let ref mut entity = manager.get_entity_mut(&message.receiver);
-        entity.handle_message(message, self);
+        // The receiver may have been removed since the message was queued
+        if let Some(entity) = manager.entities.get_mut(&message.receiver) {
+            entity.handle_message(message, self);
+        }
     }
 
     pub fn queue_message(&mut self, sender: EntityId, receiver: EntityId, message: Message) {
@@ -481,3 +658,173 @@ impl MessageDispatcher {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::sprite::{AnimatedSprite, ENEMIES, PLAYER_DOWN, PLAYER_LEFT, PLAYER_UP};
+    use crate::input::keyboard::KeyBoard;
+    use crate::level::{room::RoomBuilder, template::TemplateRegistry};
+    use enemy::Enemy;
+    use player::Player;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn room() -> Room {
+        template_room("empty.png")
+    }
+
+    fn template_room(name: &str) -> Room {
+        let templates = TemplateRegistry::load(&TemplateRegistry::default_dir()).unwrap();
+        let template = templates.template(name).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        RoomBuilder::new().build(template, &mut rng).unwrap().1
+    }
+
+    fn player(pos: Vector2<f32>) -> Box<dyn Entity> {
+        let sprites: [(Direction, &Vec<&'static Sprite>); 4] = [
+            (Direction::UP, &PLAYER_UP),
+            (Direction::DOWN, &PLAYER_DOWN),
+            (Direction::LEFT, &PLAYER_LEFT),
+            (Direction::RIGHT, &PLAYER_LEFT),
+        ];
+        let sprites = sprites
+            .iter()
+            .map(|(direction, sprites)| {
+                (*direction, AnimatedSprite::new(sprites.to_vec(), vec![5]))
+            })
+            .collect();
+        let keyboard = Rc::new(RefCell::new(KeyBoard::new()));
+        let mut player = Player::new(1., sprites, keyboard, PLAYER_ID);
+        player.set_pos(pos);
+        Box::new(player)
+    }
+
+    fn enemy(pos: Vector2<f32>) -> Box<dyn Entity> {
+        Box::new(Enemy::new(
+            pos,
+            0.5,
+            AnimatedSprite::new(ENEMIES.to_vec(), vec![5]),
+        ))
+    }
+
+    fn projectile(pos: Vector2<f32>) -> Box<dyn Entity> {
+        Box::new(Projectile::new(pos, (1., 0.).into(), 4., INVALID_ID))
+    }
+
+    // One collision pass, returns every message it caused in the order it was delivered
+    fn collide(manager: &mut EntityManager, room: &Room) -> Vec<Telegram> {
+        let mut dispatcher = MessageDispatcher::new();
+        manager.check_collisions(room, &mut dispatcher);
+        let mut delivered = Vec::new();
+        while let Some(message) = dispatcher.messages.pop_front() {
+            delivered.push(message);
+            dispatcher.discharge(manager, message);
+        }
+        delivered
+    }
+
+    fn damage_to(delivered: &[Telegram], receiver: EntityId) -> u32 {
+        delivered
+            .iter()
+            .filter(|telegram| telegram.receiver == receiver)
+            .map(|telegram| match telegram.message {
+                Message::Damage(amount) => amount,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    fn is_removed(manager: &EntityManager, id: EntityId) -> bool {
+        manager.get_entity(&id).unwrap().is_removed()
+    }
+
+    #[test]
+    fn projectiles_damage_enemies() {
+        let room = room();
+        let mut manager = EntityManager::new();
+        manager.add_entity(enemy((32., 32.).into()));
+        manager.add_entity(projectile((40., 42.).into()));
+        let (enemy_id, projectile_id) = (FIRST_FREE_ID, FIRST_FREE_ID + 1);
+
+        let delivered = collide(&mut manager, &room);
+        assert_eq!(damage_to(&delivered, enemy_id), 1);
+        assert!(is_removed(&manager, projectile_id));
+        assert!(!is_removed(&manager, enemy_id));
+
+        // The projectile is used up, the enemy dies to the third hit
+        for _ in 0..2 {
+            manager.add_entity(projectile((40., 42.).into()));
+        }
+        let delivered = collide(&mut manager, &room);
+        assert_eq!(damage_to(&delivered, enemy_id), 2);
+        assert!(is_removed(&manager, enemy_id));
+    }
+
+    #[test]
+    fn projectiles_pass_through_the_player() {
+        let room = room();
+        let mut manager = EntityManager::new();
+        manager.add_entity(player((32., 32.).into()));
+        manager.add_entity(projectile((40., 42.).into()));
+        assert!(collide(&mut manager, &room).is_empty());
+        assert!(!is_removed(&manager, FIRST_FREE_ID));
+    }
+
+    #[test]
+    fn hostiles_do_not_collide_with_each_other() {
+        let room = room();
+        let mut manager = EntityManager::new();
+        manager.add_entity(enemy((32., 32.).into()));
+        manager.add_entity(enemy((36., 34.).into()));
+        assert!(collide(&mut manager, &room).is_empty());
+        assert_eq!(manager.hostile_count(), 2);
+    }
+
+    #[test]
+    fn enemies_hurt_the_player_on_contact_and_push_it_away() {
+        let room = room();
+        let mut manager = EntityManager::new();
+        manager.add_entity(player((32., 32.).into()));
+        manager.add_entity(enemy((38., 32.).into()));
+        let enemy_id = FIRST_FREE_ID;
+
+        let delivered = collide(&mut manager, &room);
+        assert_eq!(damage_to(&delivered, PLAYER_ID), 1);
+        assert_eq!(damage_to(&delivered, enemy_id), 0);
+        let collider = |id| manager.get_entity(&id).unwrap().collider().unwrap();
+        assert!(!collider(PLAYER_ID).intersects(&collider(enemy_id)));
+    }
+
+    #[test]
+    fn projectiles_stop_on_solid_tiles() {
+        let room = room();
+        let mut dispatcher = MessageDispatcher::new();
+        // Flies left along the third row, into the wall on the left edge of the room
+        let mut projectile = Projectile::new((56., 40.).into(), (-1., 0.).into(), 4., 10);
+        let mut ticks = 0;
+        while !projectile.is_removed() {
+            projectile.update(&room, &mut dispatcher);
+            assert!(projectile.absolute_pos().x >= 16, "flew into the wall");
+            ticks += 1;
+            assert!(ticks < 100, "projectile never stopped");
+        }
+        assert_eq!(projectile.absolute_pos().x, 16);
+    }
+
+    #[test]
+    fn enemies_lose_the_player_in_the_brush() {
+        let room = template_room("hazards.png");
+        let mut manager = EntityManager::new();
+        manager.add_entity(player((13., 14.).into()));
+        assert_eq!(manager.target(&room), Some((21., 25.).into()));
+
+        // Standing in the brush along the top wall
+        manager
+            .get_entity_mut(&PLAYER_ID)
+            .set_pos((108., 10.).into());
+        assert!(room.tile_at((116., 21.).into()).hides);
+        assert_eq!(manager.target(&room), None);
+    }
+}
@@ -38,7 +38,6 @@ impl Force {
 
 pub struct MovingComponent {
     position: Vector2<f32>,
-    old_pos: Vector2<f32>,
     velocity: Vector2<f32>,
     thrust: Force,
     forces: Vec<Force>,
@@ -59,6 +58,22 @@ impl MovingComponent {
         self.thrust = thrust;
     }
 
+    pub fn velocity(&self) -> Vector2<f32> {
+        self.velocity
+    }
+
+    pub fn set_velocity(&mut self, velocity: Vector2<f32>) {
+        self.velocity = velocity;
+    }
+
+    pub fn max_speed(&self) -> f32 {
+        self.max_speed
+    }
+
+    pub fn max_force(&self) -> f32 {
+        self.max_force
+    }
+
     pub fn pos(&self) -> Vector2<f32> {
         self.position
     }
@@ -67,13 +82,16 @@ impl MovingComponent {
         self.position = pos;
     }
 
-    pub fn reset_pos(&mut self) {
-        self.position = self.old_pos;
+    pub fn add_force(&mut self, force: Force) {
+        self.forces.push(force);
+    }
+
+    pub fn has_forces(&self) -> bool {
+        !self.forces.is_empty()
     }
 
     pub fn new(mass: f32, max_speed: f32, max_force: f32) -> Self {
         MovingComponent {
-            old_pos: (0., 0.).into(),
             position: (0., 0.).into(),
             velocity: (0., 0.).into(),
             thrust: Force::new((0., 0.).into(), 0),
@@ -98,21 +116,21 @@ impl MovingComponent {
         }
         self.velocity += acceleration;
         if self.velocity.magnitude() > self.max_speed {
-            self.velocity.normalize_to(self.max_speed);
+            self.velocity = self.velocity.normalize_to(self.max_speed);
         }
-        self.old_pos = self.position;
         self.position += self.velocity;
         return acceleration.magnitude2() > 0.000001
     }
 
     fn calculate(&self) -> Vector2<f32> {
         let mut steering_force = (0., 0.).into();
-        if !self.thrust.depleeted() && self.thrust.magnitude2 > 0.000001 {
-            self.accumulate_force(&mut steering_force, self.thrust.force);
-        }
+        // External forces (e.g. knockback) take priority over the entity's own thrust
         for force in &self.forces {
             self.accumulate_force(&mut steering_force, force.force);
         }
+        if !self.thrust.depleeted() && self.thrust.magnitude2 > 0.000001 {
+            self.accumulate_force(&mut steering_force, self.thrust.force);
+        }
         steering_force
     }
 
@@ -127,7 +145,7 @@ impl MovingComponent {
 
         let magnitude_to_add = force_to_add.magnitude();
 
-        if magnitude_to_add < magnitude_so_far {
+        if magnitude_to_add < magnitude_remaining {
             *running_total += force_to_add;
         } else {
             *running_total += force_to_add.normalize_to(magnitude_remaining);
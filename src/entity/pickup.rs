@@ -0,0 +1,111 @@
+use crate::entity::{
+    Collider, CollisionKind, Entity, EntityId, Message, MessageDispatcher, Telegram, INVALID_ID,
+    PLAYER_ID,
+};
+use crate::graphics::sprite::{Sprite, HEART, SPRITE_SIZE_F32};
+use crate::level::room::Room;
+use cgmath::Vector2;
+use image::{RgbaImage, SubImage};
+
+const HEART_HIT_POINTS: u32 = 1;
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum PickupKind {
+    Heart,
+}
+
+// Loot lying on the floor until the player walks over it and can use it, kept in its
+// room when the player leaves
+pub struct Pickup {
+    position: Vector2<f32>,
+    kind: PickupKind,
+    sprite: &'static Sprite,
+    id: EntityId,
+    removed: bool,
+}
+
+impl Pickup {
+    pub fn new(position: Vector2<f32>, kind: PickupKind) -> Self {
+        let sprite = match kind {
+            PickupKind::Heart => &HEART,
+        };
+        Pickup {
+            position,
+            kind,
+            sprite,
+            id: INVALID_ID,
+            removed: false,
+        }
+    }
+}
+
+impl Entity for Pickup {
+    fn update(&mut self, _room: &Room, _dispatcher: &mut MessageDispatcher) {}
+
+    fn is_persistent(&self) -> bool {
+        true
+    }
+
+    fn sprite_view(&self) -> Option<SubImage<&RgbaImage>> {
+        Some(self.sprite.view())
+    }
+
+    fn remove(&mut self) {
+        self.removed = true;
+    }
+
+    fn is_removed(&self) -> bool {
+        self.removed
+    }
+
+    fn relative_pos(&self, offset: Vector2<f32>) -> Vector2<i32> {
+        (self.position - offset).cast().unwrap()
+    }
+
+    fn absolute_pos(&self) -> Vector2<i32> {
+        self.position.cast().unwrap()
+    }
+
+    fn collider(&self) -> Option<Collider> {
+        Some(Collider::new(
+            self.position + Vector2::new(SPRITE_SIZE_F32 / 4., SPRITE_SIZE_F32 / 4.),
+            (SPRITE_SIZE_F32 / 2., SPRITE_SIZE_F32 / 2.).into(),
+            CollisionKind::Trigger,
+        ))
+    }
+
+    fn id(&self) -> EntityId {
+        self.id
+    }
+
+    fn set_id(&mut self, id: EntityId) {
+        self.id = id;
+    }
+
+    fn handle_message(&mut self, message: Telegram, dispatcher: &mut MessageDispatcher) {
+        let Telegram {
+            sender,
+            receiver: _,
+            message,
+        } = message;
+        match message {
+            Message::Collides if sender == PLAYER_ID => {
+                let message = match self.kind {
+                    PickupKind::Heart => Message::Heal(HEART_HIT_POINTS),
+                };
+                self.send_message(message, PLAYER_ID, dispatcher);
+            }
+            Message::PickedUp => self.remove(),
+            _ => {}
+        }
+    }
+
+    fn send_message(
+        &self,
+        message: Message,
+        receiver: EntityId,
+        dispatcher: &mut MessageDispatcher,
+    ) {
+        dispatcher.queue_message(self.id(), receiver, message);
+    }
+}
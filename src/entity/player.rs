@@ -1,11 +1,12 @@
 use crate::entity::{
-    Collider, CollisionKind, Direction, Entity, EntityId, Message, MessageDispatcher, Telegram, ENTITY_MANAGER_ID, PLAYER_ID,
+    Collider, CollisionKind, Direction, Entity, EntityId, Message, MessageDispatcher, Telegram, ENTITY_MANAGER_ID, GAME_ID, PLAYER_ID,
+    health::Health,
     state::{State, StateMachine},
     moving_component::{MovingComponent, Force},
 };
 use crate::graphics::{
     screen::Screen,
-    sprite::{AnimatedSprite, SPRITE_SIZE_F32, SPRITE_SIZE_U32},
+    sprite::{AnimatedSprite, SPRITE_SIZE_F32},
 };
 use crate::input::keyboard::KeyBoard;
 use crate::level::room::Room;
@@ -15,86 +16,76 @@ use piston::input::Key;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+const PLAYER_HIT_POINTS: u32 = 6;
+const INVULNERABILITY_FRAMES: u32 = 60;
+const BLINK_FRAMES: u32 = 4;
+const DYING_FRAMES: u32 = 30;
+const FALLING_FRAMES: u32 = 30;
+const PIT_DAMAGE: u32 = 1;
+
 #[derive(Clone, Copy, Eq, PartialEq)]
 enum PlayerState {
     Walking,
     Flying,
     Damaged,
+    Falling,
     Dying,
 }
 
 impl State<Player> for PlayerState {
-    fn enter(&self, owner: &mut Player, dispatcher: &mut MessageDispatcher) {}
-    fn execute(&self, owner: &mut Player, dispatcher: &mut MessageDispatcher) {
+    fn enter(&self, owner: &mut Player, _dispatcher: &mut MessageDispatcher) {
         match *self {
-            PlayerState::Walking => {
-                owner.collides = false;
-                let mut normalize = false;
-                owner.direction = Direction::empty();
-                let mut force: Vector2<f32> = (0., 0.).into();
-                if owner.keyboard.borrow().up {
-                    force.y -= 1.;
-                    owner.direction |= Direction::UP;
-                    owner.sprite_direction = Direction::UP;
-                    normalize = true;
-                }
-                if owner.keyboard.borrow().down {
-                    force.y += 1.;
-                    owner.direction |= Direction::DOWN;
-                    owner.sprite_direction = Direction::DOWN;
-                    normalize = true;
-                }
-                if owner.keyboard.borrow().left {
-                    force.x -= 1.;
-                    owner.direction |= Direction::LEFT;
-                    owner.sprite_direction = Direction::LEFT;
-                    normalize = true;
-                }
-                if owner.keyboard.borrow().right {
-                    force.x += 1.;
-                    owner.direction |= Direction::RIGHT;
-                    owner.sprite_direction = Direction::RIGHT;
-                    normalize = true;
-                }
-                if normalize {
-                    owner.moving.set_thrust(Force::new(force.normalize(), 2));
-                }
-
+            PlayerState::Damaged => owner.health.set_invulnerable(INVULNERABILITY_FRAMES),
+            PlayerState::Falling => {
+                owner.falling_timer = FALLING_FRAMES;
+                owner.moving.set_thrust(Force::new((0., 0.).into(), 0));
                 owner.shoot_direction = None;
-                let mut proj_heading = None;
-                if owner.keyboard.borrow().keys.contains(&Key::W) {
-                    owner.shoot_direction = Some(Direction::UP);
-                    proj_heading = Some((0., -1.).into());
-                }
-                if owner.keyboard.borrow().keys.contains(&Key::S) {
-                    owner.shoot_direction = Some(Direction::DOWN);
-                    proj_heading = Some((0., 1.).into());
-                }
-                if owner.keyboard.borrow().keys.contains(&Key::A) {
-                    owner.shoot_direction = Some(Direction::LEFT);
-                    proj_heading = Some((-1., 0.).into());
+            }
+            PlayerState::Dying => {
+                owner.dying_timer = DYING_FRAMES;
+                owner.moving.set_thrust(Force::new((0., 0.).into(), 0));
+                owner.shoot_direction = None;
+            }
+            _ => {}
+        }
+    }
+    fn execute(&self, owner: &mut Player, dispatcher: &mut MessageDispatcher) {
+        match *self {
+            PlayerState::Walking => owner.handle_input(dispatcher),
+            PlayerState::Damaged => {
+                owner.handle_input(dispatcher);
+                if !owner.health.is_invulnerable() {
+                    owner.next_state = Some(PlayerState::Walking);
                 }
-                if owner.keyboard.borrow().keys.contains(&Key::D) {
-                    owner.shoot_direction = Some(Direction::RIGHT);
-                    proj_heading = Some((1., 0.).into());
+            }
+            PlayerState::Falling => {
+                owner.falling_timer = owner.falling_timer.saturating_sub(1);
+                if owner.falling_timer == 0 {
+                    owner.moving.set_pos(owner.safe_pos);
+                    dispatcher.queue_message(PLAYER_ID, PLAYER_ID, Message::Damage(PIT_DAMAGE));
+                    owner.next_state = Some(PlayerState::Walking);
                 }
-
-                if let Some(heading) = proj_heading {
-                    dispatcher.queue_message(
-                        PLAYER_ID,
-                        ENTITY_MANAGER_ID,
-                        Message::SpawnEntity(owner.middle_point(), heading, 4.0),
-                    );
+            }
+            PlayerState::Dying => {
+                owner.dying_timer = owner.dying_timer.saturating_sub(1);
+                if owner.dying_timer == 0 {
+                    dispatcher.queue_message(PLAYER_ID, GAME_ID, Message::Died);
+                    owner.remove();
                 }
             }
             _ => {}
         }
     }
-    fn exit(&self, owner: &mut Player, dispatcher: &mut MessageDispatcher) {}
+    fn exit(&self, _owner: &mut Player, _dispatcher: &mut MessageDispatcher) {}
 }
 
 pub struct Player {
     moving: MovingComponent,
+    health: Health,
+    dying_timer: u32,
+    falling_timer: u32,
+    // Last position without hazards under the player, where they come back out of pits
+    safe_pos: Vector2<f32>,
     direction: Direction,
     sprite_direction: Direction,
     shoot_direction: Option<Direction>,
@@ -104,6 +95,7 @@ pub struct Player {
     id: EntityId,
     keyboard: Rc<RefCell<KeyBoard>>,
     states: Option<StateMachine<Self, PlayerState>>,
+    next_state: Option<PlayerState>,
 }
 
 impl Player {
@@ -119,6 +111,10 @@ impl Player {
                 max_speed,
                 5.0, // max_force
             ),
+            health: Health::new(PLAYER_HIT_POINTS),
+            dying_timer: 0,
+            falling_timer: 0,
+            safe_pos: (0., 0.).into(),
             direction: Direction::RIGHT,
             sprite_direction: Direction::RIGHT,
             shoot_direction: None,
@@ -128,37 +124,108 @@ impl Player {
             id,
             keyboard,
             states: Some(StateMachine::new(None, PlayerState::Walking)),
+            next_state: None,
         }
     }
 
-    fn collision(&mut self, room: &Room) -> bool {
+    fn handle_input(&mut self, dispatcher: &mut MessageDispatcher) {
+        self.collides = false;
+        let mut normalize = false;
+        self.direction = Direction::empty();
+        let mut force: Vector2<f32> = (0., 0.).into();
+        if self.keyboard.borrow().up {
+            force.y -= 1.;
+            self.direction |= Direction::UP;
+            self.sprite_direction = Direction::UP;
+            normalize = true;
+        }
+        if self.keyboard.borrow().down {
+            force.y += 1.;
+            self.direction |= Direction::DOWN;
+            self.sprite_direction = Direction::DOWN;
+            normalize = true;
+        }
+        if self.keyboard.borrow().left {
+            force.x -= 1.;
+            self.direction |= Direction::LEFT;
+            self.sprite_direction = Direction::LEFT;
+            normalize = true;
+        }
+        if self.keyboard.borrow().right {
+            force.x += 1.;
+            self.direction |= Direction::RIGHT;
+            self.sprite_direction = Direction::RIGHT;
+            normalize = true;
+        }
+        if normalize {
+            let thrust = force.normalize() * self.moving.max_force();
+            self.moving.set_thrust(Force::new(thrust, 2));
+        }
+
+        self.shoot_direction = None;
+        let mut proj_heading = None;
+        if self.keyboard.borrow().keys.contains(&Key::W) {
+            self.shoot_direction = Some(Direction::UP);
+            proj_heading = Some((0., -1.).into());
+        }
+        if self.keyboard.borrow().keys.contains(&Key::S) {
+            self.shoot_direction = Some(Direction::DOWN);
+            proj_heading = Some((0., 1.).into());
+        }
+        if self.keyboard.borrow().keys.contains(&Key::A) {
+            self.shoot_direction = Some(Direction::LEFT);
+            proj_heading = Some((-1., 0.).into());
+        }
+        if self.keyboard.borrow().keys.contains(&Key::D) {
+            self.shoot_direction = Some(Direction::RIGHT);
+            proj_heading = Some((1., 0.).into());
+        }
+
+        if let Some(heading) = proj_heading {
+            dispatcher.queue_message(
+                PLAYER_ID,
+                ENTITY_MANAGER_ID,
+                Message::SpawnEntity(self.middle_point(), heading, 4.0),
+            );
+        }
+    }
+
+    fn change_state(&mut self, state: PlayerState, dispatcher: &mut MessageDispatcher) {
+        let mut states = self.states.take().unwrap();
+        states.change_state(self, state, dispatcher);
+        self.states = Some(states);
+    }
+
+    // Spikes hurt as soon as the player touches them, pits only once they stand above them
+    fn check_hazards(&mut self, room: &Room, dispatcher: &mut MessageDispatcher) {
         let collider = self.collider().unwrap();
-        let xy = collider.origin.cast::<i32>().unwrap();
-        let xy0 = xy / SPRITE_SIZE_U32 as i32;
-        let size_minus_one = collider.dimensions.x as i32 - 1;
-        let xy7 = (xy + Vector2::new(size_minus_one, size_minus_one)) / SPRITE_SIZE_U32 as i32;
-
-        if /*self.direction.contains(Direction::UP)  &&*/
-            (room.get_tile(xy0.x, xy0.y).solid || room.get_tile(xy7.x, xy0.y).solid) {
-                return true
-            }
-        
-        if /*self.direction.contains(Direction::DOWN)  &&*/
-            (room.get_tile(xy0.x, xy7.y).solid || room.get_tile(xy7.x, xy7.y).solid) {
-                return true
-            }
-        
-        if /*self.direction.contains(Direction::RIGHT)  &&*/
-            (room.get_tile(xy7.x, xy0.y).solid || room.get_tile(xy7.x, xy7.y).solid) {
-                return true
-            }
-        
-        if /*self.direction.contains(Direction::LEFT)  &&*/
-            (room.get_tile(xy0.x, xy0.y).solid || room.get_tile(xy0.x, xy7.y).solid) {
-                return true
-            }
-        
-        false
+        let tiles = collider.room_tiles(room);
+        if room.tile_at(collider.center()).pit {
+            self.next_state = Some(PlayerState::Falling);
+        } else if tiles.iter().all(|tile| tile.is_safe()) {
+            self.safe_pos = self.moving.pos();
+        }
+        let damage = tiles.iter().map(|tile| tile.damage).max().unwrap_or(0);
+        if damage > 0 {
+            dispatcher.queue_message(PLAYER_ID, PLAYER_ID, Message::Damage(damage));
+        }
+    }
+
+    fn collision(&mut self, room: &Room) -> bool {
+        self.collider().unwrap().collides_with_room(room)
+    }
+
+    // Moves along each axis separately so sliding along walls still works
+    fn move_axis_separated(&mut self, old_pos: Vector2<f32>, new_pos: Vector2<f32>, room: &Room) {
+        self.moving.set_pos((new_pos.x, old_pos.y).into());
+        if self.collision(room) {
+            self.moving.set_pos(old_pos);
+        }
+        let old_pos = self.moving.pos();
+        self.moving.set_pos((old_pos.x, new_pos.y).into());
+        if self.collision(room) {
+            self.moving.set_pos(old_pos);
+        }
     }
 
     fn middle_point(&self) -> Vector2<f32> {
@@ -179,29 +246,42 @@ impl Player {
 impl Entity for Player {
     fn move_entity(&mut self, forces: &[Force], room: &Room) -> bool {
         let old_pos = self.moving.pos();
+        let speed = room.tile_at(self.collider().unwrap().center()).speed;
         let moved = self.moving.update(forces, true);
-        let new_pos  = self.moving.pos();
-        self.moving.set_pos((new_pos.x, old_pos.y).into());
-        if self.collision(&room) {
-            println!("Collision1");
-            self.moving.set_pos(old_pos);
-        }
+        let new_pos = old_pos + (self.moving.pos() - old_pos) * speed;
+        self.move_axis_separated(old_pos, new_pos, room);
+        moved
+    }
+
+    fn push_out(&mut self, offset: Vector2<f32>, room: &Room) {
         let old_pos = self.moving.pos();
-        self.moving.set_pos((old_pos.x, new_pos.y).into());
-        if self.collision(&room) {
-            println!("Collision2");
-            self.moving.set_pos(old_pos);
+        self.move_axis_separated(old_pos, old_pos + offset, room);
+    }
+
+    fn apply_force(&mut self, force: Force) {
+        // Don't stack knockbacks while one is still playing out
+        if !self.moving.has_forces() {
+            self.moving.add_force(force);
         }
-        println!("-----");
-        moved
     }
 
     fn update(&mut self, room: &Room, dispatcher: &mut MessageDispatcher) {
+        self.health.update();
         let states = self.states.take().unwrap();
         states.update(self, dispatcher);
         self.states = Some(states);
+        if let Some(state) = self.next_state.take() {
+            self.change_state(state, dispatcher);
+        }
         let update_sprite = self.moving.thrust().has_magnitude();
         self.move_entity(&[], room);
+        let states = self.states.as_ref().unwrap();
+        if states.is_in_state(&PlayerState::Walking) || states.is_in_state(&PlayerState::Damaged) {
+            self.check_hazards(room, dispatcher);
+            if let Some(state) = self.next_state.take() {
+                self.change_state(state, dispatcher);
+            }
+        }
         if update_sprite {
             self.sprite_mut().update();
         } else {
@@ -214,14 +294,17 @@ impl Entity for Player {
     }
 
     fn render(&self, screen: &mut Screen, offset: Vector2<f32>) {
+        if self.falling_timer > 0 {
+            return;
+        }
+        // Blink while invulnerable
+        if (self.health.invulnerable_frames() / BLINK_FRAMES) % 2 == 1 {
+            return;
+        }
         let flip = self.shoot_direction.unwrap_or(self.direction).contains(Direction::LEFT);
         self.render_impl(screen, offset, flip);
     }
 
-    fn reset_pos(&mut self) {
-        self.moving.reset_pos();
-    }
-
     fn remove(&mut self) {
         self.removed = true;
     }
@@ -249,7 +332,7 @@ impl Entity for Player {
 
     fn collides_with(&mut self, other: &Option<Collider>) -> bool {
         if let Some(ref collider) = other {
-            if collider.hostile() {
+            if !collider.friendly() {
                 let collides = self.collider().unwrap().intersects(collider);
                 self.collides |= collides;
                 return collides;
@@ -266,9 +349,134 @@ impl Entity for Player {
         self.id
     }
 
-    fn handle_message(&mut self, _message: Telegram, _dispatcher: &mut MessageDispatcher) {}
+    fn handle_message(&mut self, message: Telegram, dispatcher: &mut MessageDispatcher) {
+        if let Message::Heal(amount) = message.message {
+            if self.health.heal(amount) {
+                dispatcher.queue_message(PLAYER_ID, message.sender, Message::PickedUp);
+            }
+        }
+        if let Message::Damage(amount) = message.message {
+            // Nothing reaches the player down in a pit
+            if self.falling_timer > 0 {
+                return;
+            }
+            if self.health.take_damage(amount) {
+                let state = if self.health.is_dead() {
+                    PlayerState::Dying
+                } else {
+                    PlayerState::Damaged
+                };
+                self.change_state(state, dispatcher);
+            }
+        }
+    }
 
     fn set_pos(&mut self, pos: Vector2<f32>) {
         self.moving.set_pos(pos);
+        self.safe_pos = pos;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::sprite::{Sprite, PLAYER_DOWN, PLAYER_LEFT, PLAYER_UP};
+    use crate::level::{room::RoomBuilder, template::TemplateRegistry};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    // Spikes in the top left, a pit in the middle, water top right and mud bottom left
+    fn room() -> Room {
+        let templates = TemplateRegistry::load(&TemplateRegistry::default_dir()).unwrap();
+        let template = templates.template("hazards.png").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        RoomBuilder::new().build(template, &mut rng).unwrap().1
+    }
+
+    fn player(pos: Vector2<f32>) -> Player {
+        let sprites: [(Direction, &Vec<&'static Sprite>); 4] = [
+            (Direction::UP, &PLAYER_UP),
+            (Direction::DOWN, &PLAYER_DOWN),
+            (Direction::LEFT, &PLAYER_LEFT),
+            (Direction::RIGHT, &PLAYER_LEFT),
+        ];
+        let sprites = sprites
+            .iter()
+            .map(|(direction, sprites)| {
+                (*direction, AnimatedSprite::new(sprites.to_vec(), vec![5]))
+            })
+            .collect();
+        let keyboard = Rc::new(RefCell::new(KeyBoard::new()));
+        let mut player = Player::new(1., sprites, keyboard, PLAYER_ID);
+        player.set_pos(pos);
+        player
+    }
+
+    // One frame, the messages the player sends to itself are delivered right away
+    fn tick(player: &mut Player, room: &Room) {
+        let mut dispatcher = MessageDispatcher::new();
+        player.update(room, &mut dispatcher);
+        while let Some(message) = dispatcher.messages.pop_front() {
+            if message.receiver == PLAYER_ID {
+                player.handle_message(message, &mut dispatcher);
+            }
+        }
+    }
+
+    // Distance covered walking right for a few frames
+    fn walk_right(room: &Room, pos: Vector2<f32>) -> f32 {
+        let mut player = player(pos);
+        player.keyboard.borrow_mut().right = true;
+        for _ in 0..8 {
+            tick(&mut player, room);
+        }
+        player.moving.pos().x - pos.x
+    }
+
+    #[test]
+    fn spikes_hurt_again_once_the_player_stops_blinking() {
+        let room = room();
+        let mut player = player((35., 30.).into());
+        tick(&mut player, &room);
+        assert_eq!(player.health.hit_points(), PLAYER_HIT_POINTS - 1);
+        for _ in 1..INVULNERABILITY_FRAMES {
+            tick(&mut player, &room);
+        }
+        assert_eq!(player.health.hit_points(), PLAYER_HIT_POINTS - 1);
+        tick(&mut player, &room);
+        assert_eq!(player.health.hit_points(), PLAYER_HIT_POINTS - 2);
+    }
+
+    #[test]
+    fn players_come_out_of_pits_where_they_last_stood_safely() {
+        let room = room();
+        let mut player = player((16., 16.).into());
+        let safe_pos = Vector2::new(83., 58.);
+        player.moving.set_pos(safe_pos);
+        tick(&mut player, &room);
+        assert_eq!(player.safe_pos, safe_pos);
+
+        player.moving.set_pos((112., 61.).into());
+        tick(&mut player, &room);
+        assert!(player
+            .states
+            .as_ref()
+            .unwrap()
+            .is_in_state(&PlayerState::Falling));
+        for _ in 0..FALLING_FRAMES {
+            tick(&mut player, &room);
+        }
+        assert_eq!(player.moving.pos(), safe_pos);
+        assert_eq!(player.health.hit_points(), PLAYER_HIT_POINTS - PIT_DAMAGE);
+    }
+
+    #[test]
+    fn water_and_mud_slow_the_player_down() {
+        let room = room();
+        let grass = walk_right(&room, (13., 14.).into());
+        let water = walk_right(&room, (173., 30.).into());
+        let mud = walk_right(&room, (29., 78.).into());
+        assert!(grass > 0.);
+        assert!((water / grass - 0.6).abs() < 1e-3);
+        assert!((mud / grass - 0.4).abs() < 1e-3);
     }
 }
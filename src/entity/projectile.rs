@@ -1,5 +1,5 @@
 use crate::entity::{
-    Collider, CollisionKind, Entity, EntityId, MessageDispatcher, Telegram,
+    Collider, CollisionKind, Entity, EntityId, Message, MessageDispatcher, Telegram, GAME_ID,
     moving_component::Force,
 };
 use crate::graphics::{
@@ -11,6 +11,8 @@ use crate::level::room::Room;
 use cgmath::Vector2;
 use image::Rgba;
 
+const PROJECTILE_DAMAGE: u32 = 1;
+
 pub struct Projectile {
     position: Vector2<f32>,
     heading: Vector2<f32>,
@@ -38,10 +40,14 @@ impl Projectile {
         }
     }
 
-    fn collision(&self, _room: &Room, _offset: Vector2<f32>) -> bool {
-        //        let xy = (self.position + offset).cast::<i32>().unwrap();
-        //        let xy0 = right_shift_vec(xy, SPRITE_SIZE_SHIFT_VALUE);
-        false
+    fn collision(&self, room: &Room, offset: Vector2<f32>) -> bool {
+        let Vector2 { x, y } = self.tile_pos(offset);
+        room.get_tile(x, y).blocks_projectiles
+    }
+
+    fn tile_pos(&self, offset: Vector2<f32>) -> Vector2<i32> {
+        let Vector2 { x, y } = (self.position + offset).map(|v| v.floor() as i32);
+        (x >> SPRITE_SIZE_SHIFT_VALUE, y >> SPRITE_SIZE_SHIFT_VALUE).into()
     }
 }
 
@@ -51,10 +57,12 @@ impl Entity for Projectile {
             self.position += forces[0].force;
             return true
         }
+        // Projectiles stop on the first wall or cover they hit
+        self.remove();
         false
     }
 
-    fn update(&mut self, room: &Room, _dispatcher: &mut MessageDispatcher) {
+    fn update(&mut self, room: &Room, dispatcher: &mut MessageDispatcher) {
         if self.position.x < 0.
             || self.position.x > (room.dimensions.x<< SPRITE_SIZE_SHIFT_VALUE) as f32
             || self.position.y < 0.
@@ -63,6 +71,10 @@ impl Entity for Projectile {
             self.remove();
         }
         let dist = self.heading * self.speed;
+        let tile = self.tile_pos(dist);
+        if room.get_tile(tile.x, tile.y).breakable {
+            dispatcher.queue_message(self.id, GAME_ID, Message::DestroyTile(tile));
+        }
         self.move_entity(&[Force::new(dist, 1)], room);
     }
 
@@ -105,6 +117,9 @@ impl Entity for Projectile {
 
     fn collides_with(&mut self, other: &Option<Collider>) -> bool {
         if let Some(ref collider) = other {
+            if !collider.hostile() {
+                return false;
+            }
             let collides = self.collider().unwrap().intersects(collider);
             self.collides |= collides;
             return collides;
@@ -120,7 +135,33 @@ impl Entity for Projectile {
         self.id
     }
 
-    fn handle_message(&mut self, _message: Telegram, _dispatcher: &mut MessageDispatcher) {}
+    fn set_id(&mut self, id: EntityId) {
+        self.id = id;
+    }
+
+    fn handle_message(&mut self, message: Telegram, dispatcher: &mut MessageDispatcher) {
+        let Telegram {
+            sender,
+            receiver: _,
+            message,
+        } = message;
+        // A projectile only ever collides with hostile colliders, which take the hit
+        if let Message::Collides = message {
+            if !self.removed {
+                self.send_message(Message::Damage(PROJECTILE_DAMAGE), sender, dispatcher);
+                self.remove();
+            }
+        }
+    }
+
+    fn send_message(
+        &self,
+        message: Message,
+        receiver: EntityId,
+        dispatcher: &mut MessageDispatcher,
+    ) {
+        dispatcher.queue_message(self.id(), receiver, message);
+    }
 
     fn set_pos(&mut self, pos: Vector2<f32>) {
         self.position = pos;
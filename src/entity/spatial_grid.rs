@@ -0,0 +1,171 @@
+use crate::entity::{Collider, EntityId};
+use crate::graphics::sprite::SPRITE_SIZE_SHIFT_VALUE;
+use cgmath::Vector2;
+use std::collections::HashMap;
+
+#[derive(Default)]
+struct Cell {
+    friendly: Vec<usize>,
+    other: Vec<usize>,
+}
+
+// Uniform grid broad phase with one cell per tile. Colliders are bucketed into every
+// cell they overlap, and only friendly/non-friendly pairs sharing a cell become candidates.
+pub struct SpatialGrid {
+    cells: HashMap<Vector2<i32>, Cell>,
+    colliders: Vec<(EntityId, Collider)>,
+}
+
+fn cell_range(collider: &Collider) -> (Vector2<i32>, Vector2<i32>) {
+    let to_cell = |v: Vector2<f32>| {
+        let Vector2 { x, y } = v.map(|c| c.floor() as i32);
+        Vector2::new(x >> SPRITE_SIZE_SHIFT_VALUE, y >> SPRITE_SIZE_SHIFT_VALUE)
+    };
+    (
+        to_cell(collider.origin),
+        to_cell(collider.origin + collider.dimensions),
+    )
+}
+
+impl SpatialGrid {
+    pub fn new() -> Self {
+        SpatialGrid {
+            cells: HashMap::new(),
+            colliders: Vec::new(),
+        }
+    }
+
+    // Cells are cleared rather than dropped so their allocations are reused every tick
+    pub fn rebuild<I: Iterator<Item = (EntityId, Collider)>>(&mut self, colliders: I) {
+        for cell in self.cells.values_mut() {
+            cell.friendly.clear();
+            cell.other.clear();
+        }
+        self.colliders.clear();
+        self.colliders.extend(colliders);
+
+        for (index, (_, collider)) in self.colliders.iter().enumerate() {
+            let (min, max) = cell_range(collider);
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let cell = self.cells.entry(Vector2::new(x, y)).or_default();
+                    if collider.friendly() {
+                        cell.friendly.push(index);
+                    } else {
+                        cell.other.push(index);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn collider(&self, index: usize) -> &(EntityId, Collider) {
+        &self.colliders[index]
+    }
+
+    /// Returns `(friendly, other)` index pairs whose cells overlap, each pair once,
+    /// sorted so the narrow phase runs in a deterministic order.
+    pub fn candidate_pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for (pos, cell) in self.cells.iter() {
+            for &friendly in cell.friendly.iter() {
+                let (friendly_min, _) = cell_range(&self.colliders[friendly].1);
+                for &other in cell.other.iter() {
+                    // A pair sharing several cells is only reported from the top left one
+                    let (other_min, _) = cell_range(&self.colliders[other].1);
+                    let first_shared = Vector2::new(
+                        friendly_min.x.max(other_min.x),
+                        friendly_min.y.max(other_min.y),
+                    );
+                    if first_shared == *pos {
+                        pairs.push((friendly, other));
+                    }
+                }
+            }
+        }
+        pairs.sort();
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::CollisionKind;
+
+    fn collider(x: f32, y: f32, size: f32, kind: CollisionKind) -> Collider {
+        Collider::new(Vector2::new(x, y), Vector2::new(size, size), kind)
+    }
+
+    fn pairs(colliders: &[Collider]) -> Vec<(usize, usize)> {
+        let mut grid = SpatialGrid::new();
+        grid.rebuild(
+            colliders
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(i, c)| (i as EntityId, c)),
+        );
+        grid.candidate_pairs()
+    }
+
+    #[test]
+    fn pairs_sharing_several_cells_are_reported_once() {
+        // Both straddle the same four cells
+        let colliders = [
+            collider(8., 8., 16., CollisionKind::Friendly),
+            collider(10., 10., 16., CollisionKind::Hostile),
+        ];
+        assert_eq!(pairs(&colliders), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn no_self_or_same_side_pairs() {
+        let colliders = [
+            collider(8., 8., 16., CollisionKind::Friendly),
+            collider(8., 8., 16., CollisionKind::Friendly),
+        ];
+        assert!(pairs(&colliders).is_empty());
+
+        let colliders = [
+            collider(8., 8., 16., CollisionKind::Hostile),
+            collider(8., 8., 16., CollisionKind::Hostile),
+        ];
+        assert!(pairs(&colliders).is_empty());
+    }
+
+    #[test]
+    fn large_colliders_meet_entities_in_any_of_their_cells() {
+        // Covers cells (0, 0) to (4, 4), the others sit in a single cell each
+        let colliders = [
+            collider(0., 0., 64., CollisionKind::Friendly),
+            collider(2., 2., 4., CollisionKind::Hostile),
+            collider(50., 2., 4., CollisionKind::Trigger),
+            collider(50., 50., 4., CollisionKind::Solid),
+            collider(100., 100., 4., CollisionKind::Hostile),
+        ];
+        assert_eq!(pairs(&colliders), vec![(0, 1), (0, 2), (0, 3)]);
+    }
+
+    #[test]
+    fn every_pair_is_unique() {
+        let mut colliders = Vec::new();
+        for i in 0..40 {
+            let kind = if i % 3 == 0 {
+                CollisionKind::Friendly
+            } else {
+                CollisionKind::Hostile
+            };
+            let pos = (i * 7 % 48) as f32;
+            colliders.push(collider(pos, 48. - pos, 4. + (i % 5) as f32 * 8., kind));
+        }
+        let pairs = pairs(&colliders);
+        let mut unique = pairs.clone();
+        unique.dedup();
+        assert_eq!(pairs, unique);
+        assert!(pairs.iter().all(|(friendly, other)| friendly != other));
+        assert!(pairs.iter().all(
+            |&(friendly, other)| colliders[friendly].friendly() && !colliders[other].friendly()
+        ));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::graphics::sprite::SPRITE_SIZE_F32;
+use crate::level::navigation::Path;
+use cgmath::{InnerSpace, Vector2};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const MIN_DISTANCE: f32 = 0.0001;
+
+pub fn seek(
+    position: Vector2<f32>,
+    velocity: Vector2<f32>,
+    target: Vector2<f32>,
+    max_speed: f32,
+) -> Vector2<f32> {
+    let to_target = target - position;
+    if to_target.magnitude2() < MIN_DISTANCE {
+        return -velocity;
+    }
+    to_target.normalize_to(max_speed) - velocity
+}
+
+pub fn flee(
+    position: Vector2<f32>,
+    velocity: Vector2<f32>,
+    target: Vector2<f32>,
+    max_speed: f32,
+) -> Vector2<f32> {
+    let from_target = position - target;
+    if from_target.magnitude2() < MIN_DISTANCE {
+        return -velocity;
+    }
+    from_target.normalize_to(max_speed) - velocity
+}
+
+// Like seek, but slows down linearly inside `slowing_radius` to stop on the target
+pub fn arrive(
+    position: Vector2<f32>,
+    velocity: Vector2<f32>,
+    target: Vector2<f32>,
+    max_speed: f32,
+    slowing_radius: f32,
+) -> Vector2<f32> {
+    let to_target = target - position;
+    let distance = to_target.magnitude();
+    if distance < MIN_DISTANCE {
+        return -velocity;
+    }
+    let speed = max_speed * (distance / slowing_radius).min(1.);
+    to_target * (speed / distance) - velocity
+}
+
+// Steers towards a point on a circle projected in front of the entity. The point
+// jitters a little every update, which results in a smooth random walk.
+pub struct Wander {
+    target: Vector2<f32>,
+    radius: f32,
+    distance: f32,
+    jitter: f32,
+    rng: StdRng,
+}
+
+impl Wander {
+    pub fn new(seed: u64) -> Self {
+        Wander {
+            target: (1., 0.).into(),
+            radius: 4.,
+            distance: 8.,
+            jitter: 1.,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn force(&mut self, velocity: Vector2<f32>, max_speed: f32) -> Vector2<f32> {
+        let jitter = Vector2::new(
+            self.rng.gen_range(-1., 1.) * self.jitter,
+            self.rng.gen_range(-1., 1.) * self.jitter,
+        );
+        self.target = (self.target + jitter).normalize_to(self.radius);
+        let heading = if velocity.magnitude2() < MIN_DISTANCE {
+            self.target.normalize()
+        } else {
+            velocity.normalize()
+        };
+        (heading * self.distance + self.target).normalize_to(max_speed) - velocity
+    }
+}
+
+// Follows a tile path found by the room navigation, seeking every waypoint in turn
+// and arriving on the last one.
+pub struct PathFollower {
+    path: Option<Path>,
+    next: usize,
+    waypoint_radius: f32,
+}
+
+impl PathFollower {
+    pub fn new(waypoint_radius: f32) -> Self {
+        PathFollower {
+            path: None,
+            next: 0,
+            waypoint_radius,
+        }
+    }
+
+    pub fn set_path(&mut self, path: Option<Path>) {
+        self.path = path;
+        self.next = 0;
+    }
+
+    pub fn goal(&self) -> Option<Vector2<i32>> {
+        self.path.as_ref().and_then(|path| path.last().cloned())
+    }
+
+    // Returns None once there is nothing left to follow
+    pub fn force(
+        &mut self,
+        position: Vector2<f32>,
+        velocity: Vector2<f32>,
+        max_speed: f32,
+    ) -> Option<Vector2<f32>> {
+        let path = self.path.as_ref()?;
+        let waypoint = |tile: Vector2<i32>| {
+            (tile.cast::<f32>().unwrap() + Vector2::new(0.5, 0.5)) * SPRITE_SIZE_F32
+        };
+        while self.next + 1 < path.len()
+            && (waypoint(path[self.next]) - position).magnitude() < self.waypoint_radius
+        {
+            self.next += 1;
+        }
+        let target = waypoint(*path.get(self.next)?);
+        if self.next + 1 == path.len() {
+            Some(arrive(position, velocity, target, max_speed, SPRITE_SIZE_F32))
+        } else {
+            Some(seek(position, velocity, target, max_speed))
+        }
+    }
+}
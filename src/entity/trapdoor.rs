@@ -0,0 +1,165 @@
+use crate::entity::{
+    Collider, CollisionKind, Entity, EntityId, Message, MessageDispatcher, Telegram, GAME_ID,
+    INVALID_ID, PLAYER_ID,
+};
+use crate::graphics::sprite::{Sprite, SPRITE_SIZE_F32, STAIRS};
+use crate::level::room::Room;
+use cgmath::Vector2;
+use image::{RgbaImage, SubImage};
+
+// Stairs to the next floor. Only a small area in the middle triggers, so the player
+// has to actually step onto them. They stay hidden and shut until the boss is beaten.
+pub struct Trapdoor {
+    position: Vector2<f32>,
+    sprite: &'static Sprite,
+    open: bool,
+    id: EntityId,
+    removed: bool,
+}
+
+impl Trapdoor {
+    pub fn new(position: Vector2<f32>) -> Self {
+        Trapdoor {
+            position,
+            sprite: &STAIRS,
+            open: false,
+            id: INVALID_ID,
+            removed: false,
+        }
+    }
+
+    pub fn with_open(mut self, open: bool) -> Self {
+        self.open = open;
+        self
+    }
+}
+
+impl Entity for Trapdoor {
+    fn update(&mut self, _room: &Room, _dispatcher: &mut MessageDispatcher) {}
+
+    fn sprite_view(&self) -> Option<SubImage<&RgbaImage>> {
+        if !self.open {
+            return None;
+        }
+        Some(self.sprite.view())
+    }
+
+    fn remove(&mut self) {
+        self.removed = true;
+    }
+
+    fn is_removed(&self) -> bool {
+        self.removed
+    }
+
+    fn relative_pos(&self, offset: Vector2<f32>) -> Vector2<i32> {
+        (self.position - offset).cast().unwrap()
+    }
+
+    fn absolute_pos(&self) -> Vector2<i32> {
+        self.position.cast().unwrap()
+    }
+
+    fn collider(&self) -> Option<Collider> {
+        if !self.open {
+            return None;
+        }
+        Some(Collider::new(
+            self.position + Vector2::new(SPRITE_SIZE_F32 / 4., SPRITE_SIZE_F32 / 4.),
+            (SPRITE_SIZE_F32 / 2., SPRITE_SIZE_F32 / 2.).into(),
+            CollisionKind::Trigger,
+        ))
+    }
+
+    fn id(&self) -> EntityId {
+        self.id
+    }
+
+    fn set_id(&mut self, id: EntityId) {
+        self.id = id;
+    }
+
+    fn handle_message(&mut self, message: Telegram, dispatcher: &mut MessageDispatcher) {
+        let Telegram {
+            sender,
+            receiver: _,
+            message,
+        } = message;
+        match message {
+            Message::Collides if sender == PLAYER_ID && self.open => {
+                self.send_message(Message::LoadLevel, GAME_ID, dispatcher);
+            }
+            Message::RoomCleared => self.open = true,
+            _ => {}
+        }
+    }
+
+    fn send_message(
+        &self,
+        message: Message,
+        receiver: EntityId,
+        dispatcher: &mut MessageDispatcher,
+    ) {
+        dispatcher.queue_message(self.id(), receiver, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step_on(trapdoor: &mut Trapdoor, dispatcher: &mut MessageDispatcher) {
+        let telegram = Telegram {
+            sender: PLAYER_ID,
+            receiver: trapdoor.id(),
+            message: Message::Collides,
+        };
+        trapdoor.handle_message(telegram, dispatcher);
+    }
+
+    #[test]
+    fn shut_trapdoors_are_hidden_and_ignore_the_player() {
+        let mut trapdoor = Trapdoor::new((0., 0.).into());
+        let mut dispatcher = MessageDispatcher::new();
+        assert!(trapdoor.collider().is_none());
+        assert!(trapdoor.sprite_view().is_none());
+        step_on(&mut trapdoor, &mut dispatcher);
+        assert!(dispatcher.poll_game_message().is_none());
+    }
+
+    #[test]
+    fn open_trapdoors_load_the_next_floor() {
+        let mut trapdoor = Trapdoor::new((0., 0.).into()).with_open(true);
+        let mut dispatcher = MessageDispatcher::new();
+        assert!(trapdoor.collider().is_some());
+        step_on(&mut trapdoor, &mut dispatcher);
+        assert!(matches!(
+            dispatcher.poll_game_message(),
+            Some(Telegram {
+                message: Message::LoadLevel,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn trapdoors_open_once_the_room_is_cleared() {
+        let mut trapdoor = Trapdoor::new((0., 0.).into());
+        let mut dispatcher = MessageDispatcher::new();
+        let telegram = Telegram {
+            sender: GAME_ID,
+            receiver: trapdoor.id(),
+            message: Message::RoomCleared,
+        };
+        trapdoor.handle_message(telegram, &mut dispatcher);
+        assert!(trapdoor.collider().is_some());
+        step_on(&mut trapdoor, &mut dispatcher);
+        assert!(matches!(
+            dispatcher.poll_game_message(),
+            Some(Telegram {
+                message: Message::LoadLevel,
+                ..
+            })
+        ));
+    }
+}
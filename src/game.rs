@@ -1,232 +1,425 @@
+use crate::editor::{Editor, EditorCommand};
 use crate::entity::{
-    PLAYER_ID, Direction, Door, EntityManager, Message, MessageDispatcher, Telegram,
+    GAME_ID, PLAYER_ID, Direction, Door, DoorState, EntityId, EntityManager, Message, MessageDispatcher, Telegram,
     enemy::Enemy,
+    pickup::{Pickup, PickupKind},
     player::Player,
+    trapdoor::Trapdoor,
 };
 use crate::graphics::{
     screen::Screen,
-    sprite::{AnimatedSprite, ENEMIES, PLAYER_DOWN, PLAYER_LEFT, PLAYER_UP, SPRITE_SIZE_U32},
+    sprite::{
+        AnimatedSprite, ENEMIES, PLAYER_DOWN, PLAYER_LEFT, PLAYER_UP, SPRITE_SIZE_SHIFT_VALUE,
+        SPRITE_SIZE_U32,
+    },
+};
+use crate::input::{
+    Key,
+    keyboard::KeyBoard,
+    replay::{InputRecorder, InputReplay},
+};
+use crate::level::{
+    room::{RoomId, RoomType, Side},
+    spawn::EnemyKind,
+    template::TemplateRegistry,
+    FloorConfig, Level, LevelError,
 };
-use crate::input::{Key, keyboard::KeyBoard};
-use crate::level::{Level, room::RoomId};
 use cgmath::Vector2;
-use piston_window::generic_event::GenericEvent;
-use piston_window::{clear, image as draw_image};
-use piston_window::{AdvancedWindow, PistonWindow, WindowSettings};
-use piston_window::{Filter, G2dTexture, Texture, TextureSettings, Transformed};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::boxed::Box;
 use std::cell::RefCell;
 use std::rc::Rc;
 
 static EXIT_KEY: &'static Key = &Key::Escape;
 static PAUSE_KEY: &'static Key = &Key::Space;
+// Held down to show the whole floor
+static MAP_KEY: &Key = &Key::Tab;
+// Opens the room editor, the editor closes on the same key
+static EDITOR_KEY: &Key = &Key::F1;
 const OFFSET_FROM_DOOR: f32 = 12.0;
+const SLIDE_FRAMES: u32 = 20;
+const FADE_FRAMES: u32 = 30;
+const GAME_OVER_TINT: image::Rgba<u8> = image::Rgba {
+    data: [120, 0, 0, 255],
+};
 pub const FRAMES_PER_SEC: i32 = 60;
 
+// Every floor gets its own layout, derived from the seed of the run (splitmix64)
+fn floor_seed(seed: u64, depth: u32) -> u64 {
+    let mut z = seed.wrapping_add(u64::from(depth).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
 #[derive(Debug)]
 enum GameState {
     Start,
     Running,
     Pause,
-    _LoadLevel,
-    LoadRoom(RoomId, bool),
+    LoadLevel,
+    // The cell of the entry door, none at the start of a floor
+    LoadRoom(RoomId, Option<Vector2<i32>>),
+    // Plays `Game::transition`, nothing updates in the meantime
+    Transition,
+    // Paints the template of the current room, see `Editor`
+    Editor,
+    GameOver,
     End,
 }
 
+enum TransitionKind {
+    // Last frame of the previous room and the direction of travel
+    Slide(image::RgbaImage, Side),
+    // Fades in from black, at the start of a floor
+    Fade,
+}
+
+struct Transition {
+    kind: TransitionKind,
+    frame: u32,
+    frames: u32,
+}
+
+impl Transition {
+    fn progress(&self) -> f32 {
+        self.frame as f32 / self.frames as f32
+    }
+
+    fn render(&self, screen: &mut Screen) {
+        match &self.kind {
+            TransitionKind::Slide(previous, side) => {
+                screen.slide(previous, side.offset(), self.progress())
+            }
+            TransitionKind::Fade => screen.fade(1. - self.progress()),
+        }
+    }
+}
+
 pub struct Game {
     window_dimensions: Vector2<u32>,
     offset: Vector2<i32>,
     scale: u32,
     state: GameState,
     pub keyboard: Rc<RefCell<KeyBoard>>,
-    window: PistonWindow,
     screen: Screen,
-    texture: G2dTexture,
     level: Level,
+    templates: TemplateRegistry,
+    seed: u64,
+    depth: u32,
     entity_manager: EntityManager,
     dispatcher: MessageDispatcher,
+    transition: Option<Transition>,
+    editor: Option<Editor>,
+    recorder: Option<InputRecorder>,
+    replay: Option<InputReplay>,
 }
 
 impl Game {
-    pub fn new(width: u32, height: u32, scale: u32) -> Game {
-        use piston_window::OpenGL;
-
-        let mut window: PistonWindow =
-            WindowSettings::new("ATOMA", (width * scale, height * scale))
-                .exit_on_esc(true)
-                .opengl(OpenGL::V3_2)
-                .build()
-                .unwrap();
-
-        window.window.window.hide_cursor(true);
-        let screen = Screen::new(width, height);
-
-        let texture: G2dTexture = Texture::from_image(
-            &mut window.factory,
-            &screen.canvas(),
-            &TextureSettings::new().mag(Filter::Nearest),
-        )
-        .unwrap();
-
-        Game {
+    /// Creates the simulation without any window attached. `scale` is only used to map
+    /// window space mouse coordinates onto the `Screen` canvas.
+    pub fn new(
+        width: u32,
+        height: u32,
+        scale: u32,
+        seed: u64,
+        templates: TemplateRegistry,
+    ) -> Result<Game, LevelError> {
+        let level = Level::new(&templates, FloorConfig::for_depth(1), floor_seed(seed, 1))?;
+        Ok(Game {
             window_dimensions: (width, height).into(),
             offset: (0, 0).into(),
             scale,
             state: GameState::Start,
             keyboard: Rc::new(RefCell::new(KeyBoard::new())),
-            window,
-            screen,
-            texture,
-            level: Level::new(27),
+            screen: Screen::new(width, height),
+            level,
+            templates,
+            seed,
+            depth: 1,
             entity_manager: EntityManager::new(),
             dispatcher: MessageDispatcher::new(),
+            transition: None,
+            editor: None,
+            recorder: None,
+            replay: None,
+        })
+    }
+
+    pub fn with_recorder(mut self, recorder: InputRecorder) -> Game {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    pub fn with_replay(mut self, replay: InputReplay) -> Game {
+        self.replay = Some(replay);
+        self
+    }
+
+    /// Steps the game state machine by one fixed update.
+    pub fn tick(&mut self) {
+        self.process_input();
+        match self.state {
+            GameState::Start => self.start(),
+            GameState::LoadRoom(id, entry) => self.enter_room(id, entry),
+            GameState::LoadLevel => self.load_level(),
+            GameState::Transition => self.update_transition(),
+            GameState::Editor => self.update_editor(),
+            GameState::Pause if self.keyboard.borrow().contains_key(PAUSE_KEY) => self.resume(),
+            GameState::Running => self.update(),
+            GameState::GameOver if self.keyboard.borrow().contains_key(EXIT_KEY) => self.stop(),
+            _ => {}
         }
     }
 
-    pub fn run(&mut self) {
-        use std::ops::Add;
-        use std::time::{Duration, Instant};
-
-        let mut last_time = Instant::now();
-        let mut timer = Instant::now();
-        let ns = 1_000_000_000.0_f64 / FRAMES_PER_SEC as f64;
-        let mut delta = 0.0_f64;
-        let mut frames = 0_u32;
-        let mut updates = 0_u32;
-        while let Some(e) = self.window.next() {
-            match self.state {
-                GameState::Start => {
-                    let player = Box::new(Player::new(
-                        1.,
-                        vec![
-                            (
-                                Direction::UP,
-                                AnimatedSprite::new(PLAYER_UP.to_vec(), vec![5, 10, 15]),
-                            ),
-                            (
-                                Direction::DOWN,
-                                AnimatedSprite::new(PLAYER_DOWN.to_vec(), vec![5, 10, 15]),
-                            ),
-                            (
-                                Direction::LEFT,
-                                AnimatedSprite::new(PLAYER_LEFT.to_vec(), vec![5, 10, 15]),
-                            ),
-                            (
-                                Direction::RIGHT,
-                                AnimatedSprite::new(PLAYER_LEFT.to_vec(), vec![5, 10, 15]),
-                            ),
-                        ],
-                        Rc::clone(&self.keyboard),
-                        PLAYER_ID,
-                    ));
-                    self.entity_manager.add_entity(player);
-
-                    self.state = GameState::LoadRoom(0, true);
-                }
-                GameState::LoadRoom(id, game_start) => {
-                    let prev_id = self.level.current_room_id();
-                    self.level.set_current_room(id);
-                    self.entity_manager.clean_up();
-                    let midle_point = self.level.current_room().middle_point();
-                    let enter_point = if game_start {
-                        midle_point
-                    } else {
-                        let pos = self
-                            .level
-                            .current_room()
-                            .load_info
-                            .doors
-                            .iter()
-                            .find(|i| {
-                                if let Some((_, room_id)) = i {
-                                    if *room_id == prev_id {
-                                        return true;
-                                    }
-                                }
-                                false
-                            })
-                            .unwrap()
-                            .unwrap()
-                            .0;
-                        let mut pos = (
-                            (pos.x * SPRITE_SIZE_U32) as f32,
-                            (pos.y * SPRITE_SIZE_U32) as f32,
-                        );
-                        if (midle_point.x - pos.0) > 0.0 {
-                            pos.0 += OFFSET_FROM_DOOR;
-                        } else if (midle_point.x - pos.0) < 0.0 {
-                            pos.0 -= OFFSET_FROM_DOOR;
-                        }
-
-                        if (midle_point.y - pos.1) > 0.0 {
-                            pos.1 += OFFSET_FROM_DOOR;
-                        } else if (midle_point.y - pos.1) < 0.0 {
-                            pos.1 -= OFFSET_FROM_DOOR;
-                        }
-                        pos.into()
-                    };
-
-                    self.entity_manager
-                        .get_entity_mut(&PLAYER_ID)
-                        .set_pos(enter_point.into());
-                    self.offset = (0, 0).into();
-                    let enemy = Box::new(Enemy::new(
-                        (32., 32.).into(),
-                        0.5,
-                        AnimatedSprite::new(ENEMIES.to_vec(), vec![30, 45, 55, 60, 65]),
-                    ));
-                    self.entity_manager.add_entity(enemy);
-
-                    let enemy = Box::new(Enemy::new(
-                        (96., 72.).into(),
-                        0.5,
-                        AnimatedSprite::new(ENEMIES.to_vec(), vec![30, 45, 55, 60, 65]),
-                    ));
-                    self.entity_manager.add_entity(enemy);
-                    self.load_room();
-                    self.state = GameState::Running;
-                }
-                GameState::Pause => {
-                    self.keyboard.borrow_mut().update(&e);
-                    if self.keyboard.borrow().contains_key(&PAUSE_KEY) {
-                        self.resume();
-                    }
-                }
-                GameState::Running => {
-                    self.keyboard.borrow_mut().update(&e);
-                    delta += last_time.elapsed().subsec_nanos() as f64 / ns;
-                    last_time = Instant::now();
-                    while delta >= 1.0 {
-                        self.update(&e);
-                        updates += 1;
-                        delta -= 1.0;
-                    }
-                    self.render(&e);
-                    frames += 1;
-
-                    if (timer.elapsed().as_secs() * 1000 + timer.elapsed().subsec_millis() as u64)
-                        > 1000
-                    {
-                        timer = timer.add(Duration::from_millis(1000));
-                        self.window
-                            .set_title(format!("ATOMA | {} ups, {} frames", updates, frames));
-                        updates = 0;
-                        frames = 0;
-                    }
+    /// Runs `ticks` updates back to back and renders the final frame, without a window.
+    pub fn run_headless(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            if self.is_finished() {
+                break;
+            }
+            self.tick();
+        }
+        self.render();
+    }
+
+    /// Whether the game advances at the fixed update rate, other states only react to input.
+    pub fn is_running(&self) -> bool {
+        matches!(self.state, GameState::Running | GameState::Transition)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, GameState::End)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn entity_pos(&self, id: EntityId) -> Option<Vector2<i32>> {
+        self.entity_manager
+            .get_entity(&id)
+            .map(|entity| entity.absolute_pos())
+    }
+
+    fn process_input(&mut self) {
+        if let Some(replay) = self.replay.as_mut() {
+            if !replay.apply(&mut self.keyboard.borrow_mut()) {
+                // Hand control back to the live input once the recording runs out
+                self.replay = None;
+                self.keyboard.borrow_mut().clear();
+            }
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(err) = recorder.record(&self.keyboard.borrow()) {
+                eprintln!("Error recording input, recording stopped: {:?}", err);
+                self.recorder = None;
+            }
+        }
+    }
+
+    fn start(&mut self) {
+        let player = Box::new(Player::new(
+            1.,
+            vec![
+                (
+                    Direction::UP,
+                    AnimatedSprite::new(PLAYER_UP.to_vec(), vec![5, 10, 15]),
+                ),
+                (
+                    Direction::DOWN,
+                    AnimatedSprite::new(PLAYER_DOWN.to_vec(), vec![5, 10, 15]),
+                ),
+                (
+                    Direction::LEFT,
+                    AnimatedSprite::new(PLAYER_LEFT.to_vec(), vec![5, 10, 15]),
+                ),
+                (
+                    Direction::RIGHT,
+                    AnimatedSprite::new(PLAYER_LEFT.to_vec(), vec![5, 10, 15]),
+                ),
+            ],
+            Rc::clone(&self.keyboard),
+            PLAYER_ID,
+        ));
+        self.entity_manager.add_entity(player);
+
+        self.state = GameState::LoadRoom(0, None);
+    }
+
+    fn enter_room(&mut self, id: RoomId, entry: Option<Vector2<i32>>) {
+        // The room being left slides out of the screen, so keep its last frame
+        let previous = entry.map(|_| {
+            self.render_scene();
+            self.screen.canvas().clone()
+        });
+        let prev_id = self.level.current_room_id();
+        let entities = self.entity_manager.take_room_entities();
+        // At the start of a floor the previous room belongs to another level
+        if entry.is_some() {
+            self.level.current_room_mut().store_entities(entities);
+        }
+        self.level.set_current_room(id);
+        let midle_point = self.level.current_room().middle_point();
+        let door = entry.and_then(|cell| {
+            self.level
+                .current_room()
+                .load_info
+                .doors
+                .iter()
+                .find(|door| door.room == prev_id && door.cell == cell)
+        });
+        let kind = match (previous, door) {
+            // The door is on the side of the new room the player comes from
+            (Some(previous), Some(door)) => TransitionKind::Slide(previous, door.side.opposite()),
+            _ => TransitionKind::Fade,
+        };
+        let enter_point = match door {
+            Some(door) => {
+                let mut pos: Vector2<f32> = (door.tile * SPRITE_SIZE_U32).cast().unwrap();
+                // Step away from the door, into the room
+                match door.side {
+                    Side::North => pos.y += OFFSET_FROM_DOOR,
+                    Side::South => pos.y -= OFFSET_FROM_DOOR,
+                    Side::West => pos.x += OFFSET_FROM_DOOR,
+                    Side::East => pos.x -= OFFSET_FROM_DOOR,
                 }
-                GameState::End => break,
-                _ => {}
+                pos
+            }
+            None => midle_point,
+        };
+
+        self.entity_manager
+            .get_entity_mut(&PLAYER_ID)
+            .set_pos(enter_point);
+        self.update_offsets();
+        let room_type = self.level.current_room().room_type;
+        if self.level.current_room().visited {
+            for entity in self.level.current_room_mut().take_entities() {
+                self.entity_manager.add_entity(entity);
             }
+        } else {
+            self.level.current_room_mut().visited = true;
+            self.spawn_enemies(room_type);
         }
+        // Rooms without anything to fight don't lock their doors
+        if self.entity_manager.hostile_count() == 0 {
+            self.level.current_room_mut().cleared = true;
+        }
+        if room_type == RoomType::Boss {
+            let cleared = self.level.current_room().cleared;
+            let trapdoor = Trapdoor::new(midle_point).with_open(cleared);
+            self.entity_manager.add_entity(Box::new(trapdoor));
+        }
+        self.load_room();
+        let frames = match kind {
+            TransitionKind::Slide(..) => SLIDE_FRAMES,
+            TransitionKind::Fade => FADE_FRAMES,
+        };
+        self.transition = Some(Transition {
+            kind,
+            frame: 0,
+            frames,
+        });
+        self.state = GameState::Transition;
     }
 
-    fn load_room(&mut self) {
-        let load_info = self.level.current_room().load_info;
-        for door_info in load_info.doors.iter() {
-            if let Some(info) = door_info {
-                let door: Door = info.into();
-                self.entity_manager.add_entity(Box::new(door));
+    fn update_transition(&mut self) {
+        let done = match self.transition.as_mut() {
+            Some(transition) => {
+                transition.frame += 1;
+                transition.frame >= transition.frames
             }
+            None => true,
+        };
+        if done {
+            self.transition = None;
+            self.state = GameState::Running;
+        }
+    }
+
+    // Fills the room from the spawn table of the current floor
+    fn spawn_enemies(&mut self, room_type: RoomType) {
+        let room = self.level.current_room();
+        let budget = self.level.config().enemy_budget(room_type);
+        let mut rng = StdRng::seed_from_u64(room.seed);
+        let spawns = self.templates.spawn_table().roll(
+            self.depth,
+            budget,
+            &room.enemy_spawn_points(),
+            &mut rng,
+        );
+        for spawn in spawns {
+            let pos = (spawn.tile * SPRITE_SIZE_U32 as i32).cast().unwrap();
+            let enemy = match spawn.enemy {
+                EnemyKind::Blob => Enemy::new(
+                    pos,
+                    spawn.speed,
+                    AnimatedSprite::new(ENEMIES.to_vec(), vec![30, 45, 55, 60, 65]),
+                ),
+            };
+            self.entity_manager.add_entity(Box::new(enemy));
+        }
+    }
+
+    // Replaces the level with the next, deeper floor and starts it from its start room
+    fn load_level(&mut self) {
+        let depth = self.depth + 1;
+        let config = FloorConfig::for_depth(depth);
+        match Level::new(&self.templates, config, floor_seed(self.seed, depth)) {
+            Ok(level) => {
+                self.level = level;
+                self.depth = depth;
+                self.state = GameState::LoadRoom(0, None);
+            }
+            Err(err) => {
+                eprintln!("Error generating floor {}: {}", depth, err);
+                self.stop();
+            }
+        }
+    }
+
+    fn load_room(&mut self) {
+        let room = self.level.current_room();
+        let locked = !room.cleared;
+        for info in room.load_info.doors.iter() {
+            // Doors to rooms the player has not seen yet start closed
+            let visited = self.level.room(info.room).map(|room| room.visited) == Some(true);
+            let state = if visited {
+                DoorState::Open
+            } else {
+                DoorState::Closed
+            };
+            let door = Door::from(info).with_state(state, locked);
+            self.entity_manager.add_entity(Box::new(door));
+        }
+    }
+
+    // Loot is rolled from the room and the tile, so replays break props the same way
+    fn destroy_tile(&mut self, tile: Vector2<i32>) {
+        let room = self.level.current_room_mut();
+        let prop = match room.destroy_tile(tile.x, tile.y) {
+            Some(prop) => prop,
+            None => return,
+        };
+        let index = (tile.y * room.dimensions.x + tile.x) as u64;
+        let mut rng = StdRng::seed_from_u64(room.seed.wrapping_add(index));
+        if rng.gen_bool(prop.loot_chance()) {
+            let pos = (tile * SPRITE_SIZE_U32 as i32).cast().unwrap();
+            let pickup = Pickup::new(pos, PickupKind::Heart);
+            self.entity_manager.add_entity(Box::new(pickup));
         }
     }
 
@@ -244,25 +437,38 @@ impl Game {
         self.keyboard.borrow_mut().clear();
     }
 
-    fn update<E: GenericEvent>(&mut self, _event: &E) {
+    fn update(&mut self) {
         if self.keyboard.borrow().contains_key(&EXIT_KEY) {
             self.stop();
         }
         if self.keyboard.borrow().contains_key(&PAUSE_KEY) {
             self.pause();
         }
+        if self.keyboard.borrow().contains_key(EDITOR_KEY) {
+            self.open_editor();
+            return;
+        }
 
         while let Some(Telegram {
-            sender: _,
+            sender,
             receiver: _,
             message,
         }) = self.dispatcher.poll_game_message()
         {
             match message {
-                Message::LoadRoom(id) => {
-                    self.state = GameState::LoadRoom(id, false);
+                Message::LoadRoom(id, cell) => {
+                    self.state = GameState::LoadRoom(id, Some(cell));
                     return;
                 }
+                Message::LoadLevel => {
+                    self.state = GameState::LoadLevel;
+                    return;
+                }
+                Message::Died if sender == PLAYER_ID => {
+                    self.state = GameState::GameOver;
+                    return;
+                }
+                Message::DestroyTile(tile) => self.destroy_tile(tile),
                 _ => {}
             }
         }
@@ -272,19 +478,79 @@ impl Game {
                 .handle_message(message, &mut self.dispatcher);
         }
 
-        self.entity_manager.check_collisions(&mut self.dispatcher);
+        self.entity_manager
+            .check_collisions(self.level.current_room(), &mut self.dispatcher);
         self.dispatcher.dispatch_messages(&mut self.entity_manager);
         self.level.update();
         self.entity_manager
             .update(&self.level.current_room(), &mut self.dispatcher);
+        if !self.level.current_room().cleared && self.entity_manager.hostile_count() == 0 {
+            self.level.current_room_mut().cleared = true;
+            self.entity_manager
+                .broadcast(GAME_ID, Message::RoomCleared, &mut self.dispatcher);
+        }
         self.update_offsets();
     }
 
-    fn update_offsets(&mut self) {
-        let player = self.entity_manager.get_entity_mut(&PLAYER_ID);
-        if player.collides() {
-            return;
+    fn open_editor(&mut self) {
+        let name = &self.level.current_room().template;
+        match self.templates.template(name) {
+            Some(template) => {
+                let editor = Editor::new(template.clone(), &self.keyboard.borrow());
+                self.editor = Some(editor);
+                self.state = GameState::Editor;
+            }
+            None => eprintln!("No template {} to edit", name),
+        }
+    }
+
+    // Tile of the current room under the mouse
+    fn mouse_tile(&self) -> Vector2<i32> {
+        let mouse_pos = self.keyboard.borrow().mouse_pos / self.scale as f64;
+        let pos = mouse_pos.cast::<i32>().unwrap() + self.offset;
+        pos.map(|c| c >> SPRITE_SIZE_SHIFT_VALUE)
+    }
+
+    fn update_editor(&mut self) {
+        let cursor = self.mouse_tile();
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let keyboard = self.keyboard.borrow();
+        let command = editor.update(&keyboard, cursor);
+        // Rooms larger than the screen scroll with the arrow keys
+        let max_offset = (self.level.dimensions() - self.window_dimensions.cast().unwrap())
+            .map(|c| c.max(0));
+        let offset = self.offset + editor.scroll(&keyboard);
+        self.offset = Vector2::new(
+            offset.x.max(0).min(max_offset.x),
+            offset.y.max(0).min(max_offset.y),
+        );
+        drop(keyboard);
+        match command {
+            Some(EditorCommand::Painted) => {
+                self.level.current_room_mut().retile(editor.template());
+            }
+            Some(EditorCommand::Save) => match self.templates.save(editor.template().clone()) {
+                Ok(path) => eprintln!("Saved room template {:?}", path),
+                Err(err) => eprintln!("Error saving room template: {}", err),
+            },
+            Some(EditorCommand::Exit) => {
+                self.editor = None;
+                self.keyboard.borrow_mut().clear();
+                self.update_offsets();
+                self.state = GameState::Running;
+            }
+            None => {}
         }
+    }
+
+    fn update_offsets(&mut self) {
+        let player = match self.entity_manager.get_entity(&PLAYER_ID) {
+            Some(player) => player,
+            None => return,
+        };
         let Vector2 { x, y } = player.absolute_pos();
         let Vector2 {
             x: lvl_width,
@@ -313,13 +579,34 @@ impl Game {
         };
     }
 
-    fn render<E: GenericEvent>(&mut self, event: &E) {
+    // The room and its entities, without anything drawn over them
+    fn render_scene(&mut self) {
         self.screen.clear();
         self.level.render(self.offset, &mut self.screen);
         self.entity_manager
             .render(&mut self.screen, self.offset.cast().unwrap());
-        self.screen.render_map(self.level.map_info());
-        let _pos = self.window.window.get_position().unwrap();
+    }
+
+    /// Renders the current frame into the software `Screen` canvas.
+    pub fn render(&mut self) {
+        self.render_scene();
+        if let Some(transition) = self.transition.as_ref() {
+            transition.render(&mut self.screen);
+        }
+        if let Some(editor) = self.editor.as_ref() {
+            let cursor = self.mouse_tile();
+            editor.render(&mut self.screen, self.offset, cursor, &self.templates);
+            return;
+        }
+        let map_info = self.level.map_info();
+        if self.keyboard.borrow().contains_key(MAP_KEY) {
+            self.screen.render_full_map(&map_info);
+        } else {
+            self.screen.render_minimap(&map_info);
+        }
+        if let GameState::GameOver = self.state {
+            self.screen.tint(GAME_OVER_TINT);
+        }
         self.screen.put_pixel(
             (self.keyboard.borrow().mouse_pos.x) as u32 / self.scale,
             (self.keyboard.borrow().mouse_pos.y) as u32 / self.scale,
@@ -327,14 +614,263 @@ impl Game {
                 data: [255, 0, 0, 255],
             },
         );
-        self.texture
-            .update(&mut self.window.encoder, &self.screen.canvas())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{room::Tiles, tile::Prop};
+    use std::collections::HashSet;
+
+    const WIDTH: u32 = 270;
+    const HEIGHT: u32 = 162;
+    const SEED: u64 = 7;
+    // Start, entering the first room and the fade-in
+    const STARTUP_TICKS: u32 = 2 + FADE_FRAMES;
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+
+    fn game(seed: u64) -> Game {
+        let templates = TemplateRegistry::load(&TemplateRegistry::default_dir()).unwrap();
+        Game::new(WIDTH, HEIGHT, 1, seed, templates).unwrap()
+    }
+
+    fn hold(game: &Game, keys: &[Key]) {
+        let keys: HashSet<Key> = keys.iter().cloned().collect();
+        game.keyboard
+            .borrow_mut()
+            .set_state(keys, HashSet::new(), (0., 0.).into());
+    }
+
+    #[test]
+    fn player_starts_on_the_spawn_point() {
+        let mut game = game(SEED);
+        game.run_headless(STARTUP_TICKS);
+        assert!(game.is_running());
+        assert_eq!(game.level.current_room_id(), 0);
+        let spawn = game.level.current_room().middle_point().cast().unwrap();
+        assert_eq!(game.entity_pos(PLAYER_ID), Some(spawn));
+    }
+
+    #[test]
+    fn player_walks_while_a_key_is_held() {
+        let mut game = game(SEED);
+        game.run_headless(STARTUP_TICKS);
+        let start = game.entity_pos(PLAYER_ID).unwrap();
+        hold(&game, &[Key::Down]);
+        game.run_headless(10);
+        let pos = game.entity_pos(PLAYER_ID).unwrap();
+        assert_eq!(pos.x, start.x);
+        assert!(
+            pos.y > start.y,
+            "player did not move: {:?} -> {:?}",
+            start,
+            pos
+        );
+    }
+
+    #[test]
+    fn nothing_moves_during_the_fade_in() {
+        let mut game = game(SEED);
+        game.run_headless(2);
+        let start = game.entity_pos(PLAYER_ID).unwrap();
+        hold(&game, &[Key::Down]);
+        game.run_headless(FADE_FRAMES - 1);
+        assert_eq!(game.entity_pos(PLAYER_ID), Some(start));
+    }
+
+    #[test]
+    fn floor_fades_in_from_black() {
+        let mut game = game(SEED);
+        game.run_headless(2);
+        let centre = (WIDTH / 2, HEIGHT / 2);
+        assert_eq!(
+            game.screen().canvas().get_pixel(centre.0, centre.1).data,
+            BLACK
+        );
+        game.run_headless(FADE_FRAMES);
+        assert_ne!(
+            game.screen().canvas().get_pixel(centre.0, centre.1).data,
+            BLACK
+        );
+        // The mouse cursor, at the top left corner
+        assert_eq!(
+            game.screen().canvas().get_pixel(0, 0).data,
+            [255, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn same_seed_renders_the_same_frames() {
+        let mut first = game(SEED);
+        let mut second = game(SEED);
+        for game in [&mut first, &mut second].iter_mut() {
+            game.run_headless(STARTUP_TICKS);
+            hold(game, &[Key::Right, Key::Down]);
+            game.run_headless(20);
+        }
+        assert_eq!(first.entity_pos(PLAYER_ID), second.entity_pos(PLAYER_ID));
+        assert!(first.screen().canvas().as_ref() == second.screen().canvas().as_ref());
+    }
+
+    fn hostiles(game: &Game) -> Vec<EntityId> {
+        let manager = &game.entity_manager;
+        manager
+            .entity_ids()
+            .into_iter()
+            .filter(|id| {
+                let entity = manager.get_entity(id).unwrap();
+                entity.collider().map(|collider| collider.hostile()) == Some(true)
+            })
+            .collect()
+    }
+
+    // Leaves the current room through its door to `room`, like touching the door does
+    fn go_through(game: &mut Game, room: RoomId) {
+        let door = *game
+            .level
+            .current_room()
+            .load_info
+            .doors
+            .iter()
+            .find(|door| door.room == room)
             .unwrap();
-        let ref texture = self.texture;
-        let scale = self.scale as f64;
-        self.window.draw_2d::<E, _, _>(&event, |c, g| {
-            clear([1.0; 4], g);
-            draw_image(texture, c.scale(scale, scale).transform, g);
-        });
+        game.state = GameState::LoadRoom(room, Some(door.cell + door.side.offset()));
+        game.run_headless(1 + SLIDE_FRAMES);
+        assert!(matches!(game.state, GameState::Running));
+        assert_eq!(game.level.current_room_id(), room);
+    }
+
+    fn kill(game: &mut Game, id: EntityId) {
+        game.dispatcher
+            .queue_message(GAME_ID, id, Message::Damage(100));
+        game.run_headless(1);
+        assert!(game.entity_manager.get_entity(&id).is_none());
+    }
+
+    #[test]
+    fn rooms_remember_their_entities_across_visits() {
+        let mut game = game(SEED);
+        game.run_headless(STARTUP_TICKS);
+        let start = game.level.current_room_id();
+        let room = game.level.current_room().load_info.doors[0].room;
+        go_through(&mut game, room);
+        let mut enemies = hostiles(&game);
+        assert!(enemies.len() >= 2, "{} enemies", enemies.len());
+        assert!(!game.level.current_room().cleared);
+
+        let killed = enemies.remove(0);
+        kill(&mut game, killed);
+        let pickup = Pickup::new((16., 16.).into(), PickupKind::Heart);
+        game.entity_manager.add_entity(Box::new(pickup));
+        let pickup = *game.entity_manager.entity_ids().last().unwrap();
+        go_through(&mut game, start);
+        assert!(hostiles(&game).is_empty());
+        go_through(&mut game, room);
+        assert_eq!(hostiles(&game), enemies);
+        assert!(game.entity_manager.get_entity(&killed).is_none());
+        assert!(game.entity_manager.get_entity(&pickup).is_some());
+        assert!(!game.level.current_room().cleared);
+
+        for enemy in enemies {
+            kill(&mut game, enemy);
+        }
+        assert!(game.level.current_room().cleared);
+        go_through(&mut game, start);
+        go_through(&mut game, room);
+        assert!(hostiles(&game).is_empty());
+        assert!(game.level.current_room().cleared);
+    }
+
+    // Locked doors block the whole tile with a solid collider
+    fn locked_doors(game: &Game) -> usize {
+        let manager = &game.entity_manager;
+        manager
+            .entity_ids()
+            .iter()
+            .filter_map(|id| manager.get_entity(id).unwrap().collider())
+            .filter(|collider| collider.solid())
+            .count()
+    }
+
+    #[test]
+    fn doors_stay_locked_until_the_last_hostile_dies() {
+        let mut game = game(SEED);
+        game.run_headless(STARTUP_TICKS);
+        let start = game.level.current_room_id();
+        assert_eq!(locked_doors(&game), 0);
+        let room = game.level.current_room().load_info.doors[0].room;
+        go_through(&mut game, room);
+        let doors = game.level.current_room().load_info.doors.len();
+        assert_eq!(locked_doors(&game), doors);
+
+        let mut enemies = hostiles(&game);
+        let last = enemies.pop().unwrap();
+        for enemy in enemies {
+            kill(&mut game, enemy);
+        }
+        game.run_headless(1);
+        assert_eq!(locked_doors(&game), doors);
+        assert!(!game.level.current_room().cleared);
+
+        kill(&mut game, last);
+        let mut cleared = false;
+        while let Some(telegram) = game.dispatcher.poll_game_message() {
+            cleared |= matches!(telegram.message, Message::RoomCleared);
+        }
+        assert!(cleared, "RoomCleared was not broadcast");
+        game.run_headless(1);
+        assert_eq!(locked_doors(&game), 0);
+
+        // Cleared rooms don't lock again, nor announce it again
+        go_through(&mut game, start);
+        go_through(&mut game, room);
+        assert_eq!(locked_doors(&game), 0);
+        game.run_headless(1);
+        while let Some(telegram) = game.dispatcher.poll_game_message() {
+            assert!(!matches!(telegram.message, Message::RoomCleared));
+        }
+    }
+
+    #[test]
+    fn broken_props_drop_hearts_as_their_tile_rolls() {
+        let props = game(SEED).templates.template("props.png").unwrap().clone();
+        let (mut drops, mut loot_props) = (0, 0);
+        for seed in 0..8 {
+            let mut game = game(seed);
+            for _ in 0..STARTUP_TICKS {
+                game.update();
+            }
+            game.level.current_room_mut().retile(&props);
+            let room = game.level.current_room();
+            let tiles: Vec<(Vector2<i32>, Prop)> = (0..room.dimensions.y)
+                .flat_map(|y| (0..room.dimensions.x).map(move |x| Vector2::new(x, y)))
+                .filter_map(|tile| {
+                    match room.tiles[(tile.y * room.dimensions.x + tile.x) as usize] {
+                        Tiles::Prop(prop, _) => Some((tile, prop)),
+                        _ => None,
+                    }
+                })
+                .collect();
+            assert!(!tiles.is_empty());
+            for (tile, prop) in tiles {
+                let room = game.level.current_room();
+                let index = (tile.y * room.dimensions.x + tile.x) as u64;
+                let mut rng = StdRng::seed_from_u64(room.seed.wrapping_add(index));
+                let drops_heart = rng.gen_bool(prop.loot_chance());
+                let entities = game.entity_manager.entity_ids().len();
+                game.destroy_tile(tile);
+                let dropped = game.entity_manager.entity_ids().len() - entities;
+                assert_eq!(dropped, drops_heart as usize);
+                assert!(prop != Prop::Rock || dropped == 0);
+                drops += dropped;
+                loot_props += (prop != Prop::Rock) as usize;
+
+                // Nothing is left to break or drop on the second hit
+                game.destroy_tile(tile);
+                assert_eq!(game.entity_manager.entity_ids().len(), entities + dropped);
+            }
+        }
+        assert!(drops > 0 && drops < loot_props);
     }
 }
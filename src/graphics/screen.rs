@@ -1,9 +1,43 @@
 use crate::entity::Direction;
-use crate::level::{MapInfo, tile::Tile};
+use crate::graphics::sprite::{Sprite, CURRENT_ROOM, NO_ROOM, ROOM};
+use crate::level::{
+    room::{RoomType, Side},
+    tile::Tile,
+    MapFog, MapInfo, MapRoom,
+};
 
 use cgmath::Vector2;
 use image::GenericImageView;
 
+// Rooms shown around the current one on the minimap, in every direction
+const MINIMAP_RADIUS: i32 = 2;
+// Size of a minimap room including the 1 pixel gap to its neighbours
+const MINIMAP_CELL: Vector2<u32> = Vector2::new(8, 6);
+const MAP_MARGIN: u32 = 2;
+const MAP_OVERLAY_TINT: image::Rgba<u8> = image::Rgba {
+    data: [0, 0, 0, 255],
+};
+
+// The map sprites are a one pixel border around a flat fill, the two colours are drawn
+// separately so rooms covering several cells can be joined
+fn map_colours(sprite: &Sprite) -> (image::Rgba<u8>, image::Rgba<u8>) {
+    let view = sprite.view();
+    (view.get_pixel(0, 0), view.get_pixel(sprite.size / 2, sprite.size / 2))
+}
+
+type MapIcon = [[u8; 3]; 3];
+
+fn map_icon(room_type: RoomType) -> Option<(MapIcon, image::Rgba<u8>)> {
+    let (icon, color) = match room_type {
+        RoomType::Boss => ([[1, 0, 1], [0, 1, 0], [1, 0, 1]], [200, 40, 40, 255]),
+        RoomType::Treasure => ([[0, 1, 0], [1, 1, 1], [0, 1, 0]], [230, 190, 40, 255]),
+        RoomType::Shop => ([[1, 1, 1], [1, 0, 1], [1, 1, 1]], [60, 190, 90, 255]),
+        RoomType::Secret => ([[1, 0, 1], [0, 0, 0], [1, 0, 1]], [170, 90, 220, 255]),
+        RoomType::Start | RoomType::Normal => return None,
+    };
+    Some((icon, image::Rgba { data: color }))
+}
+
 pub struct Screen {
     pub dimensions: Vector2<u32>,
     offset: Vector2<i32>,
@@ -51,43 +85,140 @@ impl Screen {
                     Direction::RIGHT => (y, tile.sprite.size - 1 - x),
                     _ => unimplemented!(),
                 };
-                self.canvas.put_pixel(
-                    xa as u32,
-                    ya as u32,
-                    tile.sprite.view().get_pixel(pixel_x, pixel_y),
-                )
+                // Magenta is transparent like in entity sprites, props are drawn over the floor
+                let pixel = tile.sprite.view().get_pixel(pixel_x, pixel_y);
+                if pixel.data == [255, 0, 255, 255] {
+                    continue;
+                }
+                self.canvas.put_pixel(xa as u32, ya as u32, pixel)
+            }
+        }
+    }
+
+    /// Draws the rooms around the current one into the top right corner.
+    pub fn render_minimap(&mut self, map_info: &MapInfo) {
+        let cells = MINIMAP_RADIUS * 2 + 1;
+        let size = Vector2::new(
+            cells as u32 * MINIMAP_CELL.x + 1,
+            cells as u32 * MINIMAP_CELL.y + 1,
+        );
+        let origin = Vector2::new(self.dimensions.x - size.x - MAP_MARGIN, MAP_MARGIN);
+        self.fill_rect(origin, size, map_colours(&NO_ROOM).1);
+        let first = map_info.current_grid_pos - Vector2::new(MINIMAP_RADIUS, MINIMAP_RADIUS);
+        for room in map_info.rooms.iter() {
+            let cell = room.grid_pos - first;
+            if cell.x < 0 || cell.y < 0 || cell.x >= cells || cell.y >= cells {
+                continue;
+            }
+            let pos = origin
+                + Vector2::new(1, 1)
+                + Vector2::new(cell.x as u32 * MINIMAP_CELL.x, cell.y as u32 * MINIMAP_CELL.y);
+            self.render_map_room(room, pos, MINIMAP_CELL - Vector2::new(1, 1), 1);
+        }
+    }
+
+    /// Dims the frame and draws the whole floor on top of it, as large as it fits.
+    pub fn render_full_map(&mut self, map_info: &MapInfo) {
+        self.tint(MAP_OVERLAY_TINT);
+        let available = self.dimensions - Vector2::new(MAP_MARGIN * 2 + 1, MAP_MARGIN * 2 + 1);
+        let grid = map_info.dimensions.cast::<u32>().unwrap();
+        if grid.x == 0 || grid.y == 0 {
+            return;
+        }
+        // Cells keep the 5:3 proportions of a room
+        let height = (available.y / grid.y).min(available.x * 3 / (grid.x * 5));
+        let cell = Vector2::new(height * 5 / 3, height);
+        let size = Vector2::new(grid.x * cell.x + 1, grid.y * cell.y + 1);
+        let origin = (self.dimensions - size) / 2;
+        self.fill_rect(origin, size, map_colours(&NO_ROOM).1);
+        for room in map_info.rooms.iter() {
+            let pos = origin
+                + Vector2::new(1, 1)
+                + Vector2::new(room.grid_pos.x as u32 * cell.x, room.grid_pos.y as u32 * cell.y);
+            self.render_map_room(room, pos, cell - Vector2::new(1, 1), (height / 5).max(1));
+        }
+    }
+
+    fn render_map_room(
+        &mut self,
+        room: &MapRoom,
+        pos: Vector2<u32>,
+        size: Vector2<u32>,
+        icon_scale: u32,
+    ) {
+        // Cells of the same room are drawn over the gap between them, without a border
+        let (mut outer_pos, mut outer_size) = (pos, size);
+        let (mut inner_pos, mut inner_size) = (pos + Vector2::new(1, 1), size - Vector2::new(2, 2));
+        for side in room.joined.iter() {
+            match side {
+                Side::North => {
+                    outer_pos.y -= 1;
+                    outer_size.y += 1;
+                    inner_pos.y -= 2;
+                    inner_size.y += 2;
+                }
+                Side::South => {
+                    outer_size.y += 1;
+                    inner_size.y += 2;
+                }
+                Side::West => {
+                    outer_pos.x -= 1;
+                    outer_size.x += 1;
+                    inner_pos.x -= 2;
+                    inner_size.x += 2;
+                }
+                Side::East => {
+                    outer_size.x += 1;
+                    inner_size.x += 2;
+                }
+            }
+        }
+        let (border, fill) = match room.fog {
+            MapFog::Current => map_colours(&CURRENT_ROOM),
+            MapFog::Visited => map_colours(&ROOM),
+            // Only the outline of rooms that were not visited yet
+            MapFog::Seen => (map_colours(&ROOM).0, map_colours(&NO_ROOM).1),
+        };
+        self.fill_rect(outer_pos, outer_size, border);
+        self.fill_rect(inner_pos, inner_size, fill);
+        let (icon, color) = match map_icon(room.room_type) {
+            Some(icon) => icon,
+            None => return,
+        };
+        let icon_size = 3 * icon_scale;
+        if icon_size > size.x || icon_size > size.y {
+            return;
+        }
+        let icon_pos = pos + (size - Vector2::new(icon_size, icon_size)) / 2;
+        for (y, row) in icon.iter().enumerate() {
+            for (x, bit) in row.iter().enumerate() {
+                if *bit == 1 {
+                    let offset = Vector2::new(x as u32, y as u32) * icon_scale;
+                    self.fill_rect(icon_pos + offset, (icon_scale, icon_scale).into(), color);
+                }
             }
         }
     }
 
-    pub fn render_map<'a>(&mut self, _map_info: MapInfo<'a>) {
-        let _x_start = 41;
-        let _y_start = 0;
-        /*for x in 0..MAP_GRID_SIZE {
-            for y in 0..MAP_GRID_SIZE {
-                if map_info.map_grid[x][y] {
-                    if (x as i32, y as i32) == map_info.current_grid_pos.into() {
-                        self.canvas.copy_from(
-                            &CURRENT_ROOM_TILE.sprite.view(),
-                            x_start + (x as u32)<< HALF_SPRITE_SIZE_SHIFT_VALUE,
-                            y_start + (y as u32)<< HALF_SPRITE_SIZE_SHIFT_VALUE,
-                        );
-                    } else {
-                        self.canvas.copy_from(
-                            &ROOM_TILE.sprite.view(),
-                            x_start + (x as u32)<< HALF_SPRITE_SIZE_SHIFT_VALUE,
-                            y_start + (y as u32)<< HALF_SPRITE_SIZE_SHIFT_VALUE,
-                        );
-                    }
-                } else {
-                    self.canvas.copy_from(
-                        &NO_ROOM_TILE.sprite.view(),
-                        x_start + (x as u32)<< HALF_SPRITE_SIZE_SHIFT_VALUE,
-                        y_start + (y as u32)<< HALF_SPRITE_SIZE_SHIFT_VALUE,
-                    );
+    pub fn fill_rect(&mut self, pos: Vector2<u32>, size: Vector2<u32>, color: image::Rgba<u8>) {
+        for y in pos.y..pos.y + size.y {
+            for x in pos.x..pos.x + size.x {
+                self.put_pixel(x, y, color);
+            }
+        }
+    }
+
+    // One pixel wide border of a rectangle, clipped to the canvas
+    pub fn outline_rect(&mut self, pos: Vector2<i32>, size: Vector2<u32>, color: image::Rgba<u8>) {
+        let end = pos + size.cast::<i32>().unwrap() - Vector2::new(1, 1);
+        for y in pos.y..=end.y {
+            for x in pos.x..=end.x {
+                let border = x == pos.x || x == end.x || y == pos.y || y == end.y;
+                if border && x >= 0 && y >= 0 {
+                    self.put_pixel(x as u32, y as u32, color);
                 }
             }
-        }*/
+        }
     }
 
     pub fn clear(&mut self) {
@@ -96,6 +227,45 @@ impl Screen {
         }
     }
 
+    // Averages every pixel of the canvas with the given color
+    pub fn tint(&mut self, color: image::Rgba<u8>) {
+        for pixel in self.canvas.pixels_mut() {
+            for (channel, tint) in pixel.data.iter_mut().zip(color.data.iter()).take(3) {
+                *channel = ((*channel as u16 + *tint as u16) / 2) as u8;
+            }
+        }
+    }
+
+    // Scrolls `previous` out of the canvas towards `-direction` by `progress` (0 to 1) of
+    // the screen size, the current canvas follows it in from `direction`
+    pub fn slide(&mut self, previous: &image::RgbaImage, direction: Vector2<i32>, progress: f32) {
+        let next = self.canvas.clone();
+        let size = self.dimensions.cast::<i32>().unwrap();
+        let shift = Vector2::new(
+            direction.x * (size.x as f32 * progress) as i32,
+            direction.y * (size.y as f32 * progress) as i32,
+        );
+        for (x, y, pixel) in self.canvas.enumerate_pixels_mut() {
+            let pos = Vector2::new(x as i32, y as i32) + shift;
+            *pixel = if pos.x >= 0 && pos.x < size.x && pos.y >= 0 && pos.y < size.y {
+                *previous.get_pixel(pos.x as u32, pos.y as u32)
+            } else {
+                let pos = pos - Vector2::new(direction.x * size.x, direction.y * size.y);
+                *next.get_pixel(pos.x as u32, pos.y as u32)
+            };
+        }
+    }
+
+    // Darkens the canvas, from untouched at 0 to black at 1
+    pub fn fade(&mut self, amount: f32) {
+        let keep = 1. - amount.clamp(0., 1.);
+        for pixel in self.canvas.pixels_mut() {
+            for channel in pixel.data.iter_mut().take(3) {
+                *channel = (*channel as f32 * keep) as u8;
+            }
+        }
+    }
+
     pub fn set_offset(&mut self, offset: Vector2<i32>) {
         self.offset = offset;
     }
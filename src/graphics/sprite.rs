@@ -4,6 +4,7 @@ use std::path::PathBuf;
 pub static SPRITE_SIZE_U32: u32 = 16;
 pub static SPRITE_SIZE_SHIFT_VALUE: u32 = 4;
 pub static HALF_SPRITE_SIZE_U32: u32 = 8;
+pub static MAP_SPRITE_SIZE_U32: u32 = 4;
 pub static SPRITE_SIZE_F32: f32 = 16.0;
 
 lazy_static! {
@@ -19,20 +20,92 @@ lazy_static! {
     static ref GRASS5: Sprite = Sprite::new(SPRITE_SIZE_U32, 10, 3, &SHEET);
     pub static ref GRASSES: Vec<&'static Sprite> =
         vec![&GRASS0, &GRASS1, &GRASS2, &GRASS3, &GRASS4, &GRASS5];
+    static ref SLAB0: Sprite = Sprite::new(SPRITE_SIZE_U32, 6, 0, &SHEET);
+    static ref SLAB1: Sprite = Sprite::new(SPRITE_SIZE_U32, 7, 0, &SHEET);
+    static ref SLAB2: Sprite = Sprite::new(SPRITE_SIZE_U32, 8, 0, &SHEET);
+    static ref SLAB3: Sprite = Sprite::new(SPRITE_SIZE_U32, 6, 1, &SHEET);
+    static ref SLAB4: Sprite = Sprite::new(SPRITE_SIZE_U32, 7, 1, &SHEET);
+    static ref SLAB5: Sprite = Sprite::new(SPRITE_SIZE_U32, 8, 1, &SHEET);
+    pub static ref SLABS: Vec<&'static Sprite> =
+        vec![&SLAB0, &SLAB1, &SLAB2, &SLAB3, &SLAB4, &SLAB5];
+    static ref RUBBLE0: Sprite = Sprite::new(SPRITE_SIZE_U32, 6, 3, &SHEET);
+    static ref RUBBLE1: Sprite = Sprite::new(SPRITE_SIZE_U32, 7, 3, &SHEET);
+    static ref RUBBLE2: Sprite = Sprite::new(SPRITE_SIZE_U32, 8, 3, &SHEET);
+    static ref RUBBLE3: Sprite = Sprite::new(SPRITE_SIZE_U32, 9, 3, &SHEET);
+    static ref RUBBLE4: Sprite = Sprite::new(SPRITE_SIZE_U32, 11, 4, &SHEET);
+    static ref RUBBLE5: Sprite = Sprite::new(SPRITE_SIZE_U32, 11, 5, &SHEET);
+    pub static ref RUBBLES: Vec<&'static Sprite> =
+        vec![&RUBBLE0, &RUBBLE1, &RUBBLE2, &RUBBLE3, &RUBBLE4, &RUBBLE5];
     pub static ref VOID: Sprite = Sprite::new(SPRITE_SIZE_U32, 18, 0, &SHEET);
     static ref CORNER0: Sprite = Sprite::new(SPRITE_SIZE_U32, 3, 0, &SHEET);
     static ref CORNER1: Sprite = Sprite::new(SPRITE_SIZE_U32, 3, 1, &SHEET);
     static ref CORNER2: Sprite = Sprite::new(SPRITE_SIZE_U32, 4, 0, &SHEET);
     static ref CORNER3: Sprite = Sprite::new(SPRITE_SIZE_U32, 4, 1, &SHEET);
     pub static ref CORNERS: Vec<&'static Sprite> = vec![&CORNER0, &CORNER1, &CORNER2, &CORNER3];
+    static ref OUTER_CORNER0: Sprite = Sprite::new(SPRITE_SIZE_U32, 0, 0, &SHEET);
+    static ref OUTER_CORNER1: Sprite = Sprite::new(SPRITE_SIZE_U32, 0, 2, &SHEET);
+    static ref OUTER_CORNER2: Sprite = Sprite::new(SPRITE_SIZE_U32, 2, 0, &SHEET);
+    static ref OUTER_CORNER3: Sprite = Sprite::new(SPRITE_SIZE_U32, 2, 2, &SHEET);
+    pub static ref OUTER_CORNERS: Vec<&'static Sprite> =
+        vec![&OUTER_CORNER0, &OUTER_CORNER1, &OUTER_CORNER2, &OUTER_CORNER3];
+    static ref EDGE0: Sprite = Sprite::new(SPRITE_SIZE_U32, 1, 2, &SHEET);
+    static ref EDGE1: Sprite = Sprite::new(SPRITE_SIZE_U32, 1, 0, &SHEET);
+    static ref EDGE2: Sprite = Sprite::new(SPRITE_SIZE_U32, 2, 1, &SHEET);
+    static ref EDGE3: Sprite = Sprite::new(SPRITE_SIZE_U32, 0, 1, &SHEET);
+    pub static ref EDGES: Vec<&'static Sprite> = vec![&EDGE0, &EDGE1, &EDGE2, &EDGE3];
+    pub static ref WALL_TOP: Sprite = Sprite::new(SPRITE_SIZE_U32, 1, 1, &SHEET);
     static ref WALL0: Sprite = Sprite::new(SPRITE_SIZE_U32, 0, 3, &SHEET);
     static ref WALL1: Sprite = Sprite::new(SPRITE_SIZE_U32, 1, 3, &SHEET);
     static ref WALL2: Sprite = Sprite::new(SPRITE_SIZE_U32, 2, 3, &SHEET);
     pub static ref WALLS: Vec<&'static Sprite> = vec![&WALL0, &WALL1, &WALL2,];
     pub static ref DOOR: Sprite = Sprite::new(SPRITE_SIZE_U32, 2, 8, &SHEET);
-    pub static ref ROOM: Sprite = Sprite::new(HALF_SPRITE_SIZE_U32, 12, 28, &SHEET);
-    pub static ref CURRENT_ROOM: Sprite = Sprite::new(HALF_SPRITE_SIZE_U32, 12, 29, &SHEET);
-    pub static ref NO_ROOM: Sprite = Sprite::new(HALF_SPRITE_SIZE_U32, 13, 28, &SHEET);
+    pub static ref OPEN_DOOR: Sprite = Sprite::new(SPRITE_SIZE_U32, 6, 7, &SHEET);
+    pub static ref LOCKED_DOOR: Sprite = Sprite::new(SPRITE_SIZE_U32, 5, 8, &SHEET);
+    pub static ref STAIRS: Sprite = Sprite::new(SPRITE_SIZE_U32, 5, 4, &SHEET);
+}
+
+lazy_static! {
+    // Sandstone walls, laid out like the walls of the main sheet
+    pub static ref WALL_SHEET: SpriteSheet = {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("res/sprites/walls.png");
+        SpriteSheet::new(path.to_str().unwrap().to_owned())
+    };
+    static ref SANDSTONE_CORNER0: Sprite = Sprite::new(SPRITE_SIZE_U32, 3, 0, &WALL_SHEET);
+    static ref SANDSTONE_CORNER1: Sprite = Sprite::new(SPRITE_SIZE_U32, 3, 1, &WALL_SHEET);
+    static ref SANDSTONE_CORNER2: Sprite = Sprite::new(SPRITE_SIZE_U32, 4, 0, &WALL_SHEET);
+    static ref SANDSTONE_CORNER3: Sprite = Sprite::new(SPRITE_SIZE_U32, 4, 1, &WALL_SHEET);
+    pub static ref SANDSTONE_CORNERS: Vec<&'static Sprite> = vec![
+        &SANDSTONE_CORNER0,
+        &SANDSTONE_CORNER1,
+        &SANDSTONE_CORNER2,
+        &SANDSTONE_CORNER3
+    ];
+    static ref SANDSTONE_OUTER_CORNER0: Sprite = Sprite::new(SPRITE_SIZE_U32, 0, 0, &WALL_SHEET);
+    static ref SANDSTONE_OUTER_CORNER1: Sprite = Sprite::new(SPRITE_SIZE_U32, 0, 2, &WALL_SHEET);
+    static ref SANDSTONE_OUTER_CORNER2: Sprite = Sprite::new(SPRITE_SIZE_U32, 2, 0, &WALL_SHEET);
+    static ref SANDSTONE_OUTER_CORNER3: Sprite = Sprite::new(SPRITE_SIZE_U32, 2, 2, &WALL_SHEET);
+    pub static ref SANDSTONE_OUTER_CORNERS: Vec<&'static Sprite> = vec![
+        &SANDSTONE_OUTER_CORNER0,
+        &SANDSTONE_OUTER_CORNER1,
+        &SANDSTONE_OUTER_CORNER2,
+        &SANDSTONE_OUTER_CORNER3
+    ];
+    static ref SANDSTONE_EDGE0: Sprite = Sprite::new(SPRITE_SIZE_U32, 1, 2, &WALL_SHEET);
+    static ref SANDSTONE_EDGE1: Sprite = Sprite::new(SPRITE_SIZE_U32, 1, 0, &WALL_SHEET);
+    static ref SANDSTONE_EDGE2: Sprite = Sprite::new(SPRITE_SIZE_U32, 2, 1, &WALL_SHEET);
+    static ref SANDSTONE_EDGE3: Sprite = Sprite::new(SPRITE_SIZE_U32, 0, 1, &WALL_SHEET);
+    pub static ref SANDSTONE_EDGES: Vec<&'static Sprite> = vec![
+        &SANDSTONE_EDGE0,
+        &SANDSTONE_EDGE1,
+        &SANDSTONE_EDGE2,
+        &SANDSTONE_EDGE3
+    ];
+    pub static ref SANDSTONE_WALL_TOP: Sprite = Sprite::new(SPRITE_SIZE_U32, 1, 1, &WALL_SHEET);
+    static ref SANDSTONE_WALL0: Sprite = Sprite::new(SPRITE_SIZE_U32, 0, 3, &WALL_SHEET);
+    static ref SANDSTONE_WALL1: Sprite = Sprite::new(SPRITE_SIZE_U32, 1, 3, &WALL_SHEET);
+    static ref SANDSTONE_WALL2: Sprite = Sprite::new(SPRITE_SIZE_U32, 2, 3, &WALL_SHEET);
+    pub static ref SANDSTONE_WALLS: Vec<&'static Sprite> =
+        vec![&SANDSTONE_WALL0, &SANDSTONE_WALL1, &SANDSTONE_WALL2];
 }
 
 lazy_static! {
@@ -65,6 +138,40 @@ lazy_static! {
     pub static ref ENEMIES: Vec<&'static Sprite> = vec![&ENEMY0, &ENEMY1, &ENEMY2];
 }
 
+lazy_static! {
+    pub static ref HAZARD_SHEET: SpriteSheet = {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("res/sprites/hazards.png");
+        SpriteSheet::new(path.to_str().unwrap().to_owned())
+    };
+    pub static ref SPIKES: Sprite = Sprite::new(SPRITE_SIZE_U32, 0, 0, &HAZARD_SHEET);
+    pub static ref PIT: Sprite = Sprite::new(SPRITE_SIZE_U32, 1, 0, &HAZARD_SHEET);
+    pub static ref WATER: Sprite = Sprite::new(SPRITE_SIZE_U32, 2, 0, &HAZARD_SHEET);
+    pub static ref MUD: Sprite = Sprite::new(SPRITE_SIZE_U32, 3, 0, &HAZARD_SHEET);
+    pub static ref BRUSH: Sprite = Sprite::new(SPRITE_SIZE_U32, 4, 0, &HAZARD_SHEET);
+}
+
+lazy_static! {
+    // The map sprites come from the older 8 pixel tileset, where they take a quarter tile
+    pub static ref TILESET: SpriteSheet = {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("res/sprites/tileset.png");
+        SpriteSheet::new(path.to_str().unwrap().to_owned())
+    };
+    pub static ref ROOM: Sprite = Sprite::new(MAP_SPRITE_SIZE_U32, 12, 28, &TILESET);
+    pub static ref CURRENT_ROOM: Sprite = Sprite::new(MAP_SPRITE_SIZE_U32, 12, 29, &TILESET);
+    pub static ref NO_ROOM: Sprite = Sprite::new(MAP_SPRITE_SIZE_U32, 13, 28, &TILESET);
+}
+
+lazy_static! {
+    pub static ref PROP_SHEET: SpriteSheet = {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("res/sprites/props.png");
+        SpriteSheet::new(path.to_str().unwrap().to_owned())
+    };
+    pub static ref ROCK: Sprite = Sprite::new(SPRITE_SIZE_U32, 0, 0, &PROP_SHEET);
+    pub static ref POT: Sprite = Sprite::new(SPRITE_SIZE_U32, 1, 0, &PROP_SHEET);
+    pub static ref CRATE: Sprite = Sprite::new(SPRITE_SIZE_U32, 2, 0, &PROP_SHEET);
+    pub static ref HEART: Sprite = Sprite::new(SPRITE_SIZE_U32, 3, 0, &PROP_SHEET);
+}
+
 /*pub static SPRITE_SIZE_U32: u32 = 8;
 pub static SPRITE_SIZE_SHIFT_VALUE: u32 = 3;
 pub static HALF_SPRITE_SIZE_U32: u32 = 4;
@@ -1,5 +1,5 @@
 use cgmath::Vector2;
-use piston::input::{Button, Key};
+use piston::input::{Button, Key, MouseButton};
 use piston_window::GenericEvent;
 use std::collections::HashSet;
 
@@ -10,6 +10,13 @@ pub struct KeyBoard {
     pub left: bool,
     pub right: bool,
     pub mouse_pos: Vector2<f64>,
+    pub mouse_buttons: HashSet<MouseButton>,
+}
+
+impl Default for KeyBoard {
+    fn default() -> KeyBoard {
+        KeyBoard::new()
+    }
 }
 
 impl KeyBoard {
@@ -21,22 +28,52 @@ impl KeyBoard {
             left: false,
             right: false,
             mouse_pos: (0., 0.).into(),
+            mouse_buttons: HashSet::new(),
         }
     }
 
     pub fn update<E: GenericEvent>(&mut self, event: &E) {
-        if let Some(Button::Keyboard(button)) = event.press_args() {
-            self.keys.insert(button);
-        };
+        match event.press_args() {
+            Some(Button::Keyboard(button)) => {
+                self.keys.insert(button);
+            }
+            Some(Button::Mouse(button)) => {
+                self.mouse_buttons.insert(button);
+            }
+            _ => {}
+        }
 
-        if let Some(Button::Keyboard(button)) = event.release_args() {
-            self.keys.remove(&button);
-        };
+        match event.release_args() {
+            Some(Button::Keyboard(button)) => {
+                self.keys.remove(&button);
+            }
+            Some(Button::Mouse(button)) => {
+                self.mouse_buttons.remove(&button);
+            }
+            _ => {}
+        }
 
         if let Some(pos) = event.mouse_cursor_args() {
             self.mouse_pos = pos.into();
         }
 
+        self.update_directions();
+    }
+
+    /// Overrides the live input state, used when replaying a recorded session.
+    pub fn set_state(
+        &mut self,
+        keys: HashSet<Key>,
+        mouse_buttons: HashSet<MouseButton>,
+        mouse_pos: Vector2<f64>,
+    ) {
+        self.keys = keys;
+        self.mouse_buttons = mouse_buttons;
+        self.mouse_pos = mouse_pos;
+        self.update_directions();
+    }
+
+    fn update_directions(&mut self) {
         self.up = self.keys.contains(&Key::Up);
         self.down = self.keys.contains(&Key::Down);
         self.left = self.keys.contains(&Key::Left);
@@ -47,7 +84,12 @@ impl KeyBoard {
         self.keys.contains(&key)
     }
 
+    pub fn is_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.contains(&button)
+    }
+
     pub fn clear(&mut self) {
         self.keys.clear();
+        self.mouse_buttons.clear();
     }
 }
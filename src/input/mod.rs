@@ -1,4 +1,5 @@
 use piston;
 
 pub mod keyboard;
-pub use piston::input::Key;
+pub mod replay;
+pub use piston::input::{Key, MouseButton};
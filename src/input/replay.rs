@@ -0,0 +1,242 @@
+use crate::input::{keyboard::KeyBoard, Key, MouseButton};
+use cgmath::Vector2;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+// File layout: a header line, a `seed <u64>` line, then one line per tick holding
+// the mouse position followed by the comma separated codes of the pressed keys and
+// of the pressed mouse buttons. Version 1 files have no mouse buttons.
+const HEADER: &str = "atoma-input 2";
+const OLD_HEADERS: [&str; 1] = ["atoma-input 1"];
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse { line: usize, reason: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "{}", err),
+            ReplayError::Parse { line, reason } => write!(f, "line {}: {}", line, reason),
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> Self {
+        ReplayError::Io(err)
+    }
+}
+
+pub struct InputFrame {
+    pub keys: HashSet<Key>,
+    pub mouse_buttons: HashSet<MouseButton>,
+    pub mouse_pos: Vector2<f64>,
+}
+
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path, seed: u64) -> io::Result<InputRecorder> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", HEADER)?;
+        writeln!(writer, "seed {}", seed)?;
+        Ok(InputRecorder { writer })
+    }
+
+    pub fn record(&mut self, keyboard: &KeyBoard) -> io::Result<()> {
+        let keys = join_codes(keyboard.keys.iter().map(|key| u32::from(*key)));
+        let buttons = join_codes(keyboard.mouse_buttons.iter().map(|button| u32::from(*button)));
+        writeln!(
+            self.writer,
+            "{} {} {} {}",
+            keyboard.mouse_pos.x, keyboard.mouse_pos.y, keys, buttons
+        )
+    }
+}
+
+fn join_codes<I: Iterator<Item = u32>>(codes: I) -> String {
+    let mut codes: Vec<u32> = codes.collect();
+    codes.sort();
+    let codes: Vec<String> = codes.iter().map(|code| code.to_string()).collect();
+    codes.join(",")
+}
+
+fn parse_codes(field: Option<&str>) -> Option<Vec<u32>> {
+    field
+        .unwrap_or("")
+        .split(',')
+        .filter(|code| !code.is_empty())
+        .map(|code| code.parse().ok())
+        .collect()
+}
+
+pub struct InputReplay {
+    seed: u64,
+    frames: VecDeque<InputFrame>,
+}
+
+impl InputReplay {
+    pub fn open(path: &Path) -> Result<InputReplay, ReplayError> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let parse_error = |line: usize, reason: &str| ReplayError::Parse {
+            line,
+            reason: reason.to_owned(),
+        };
+
+        match lines.next().transpose()? {
+            Some(header) if header == HEADER || OLD_HEADERS.contains(&header.as_str()) => {}
+            _ => return Err(parse_error(1, "missing header")),
+        }
+        let seed = match lines.next().transpose()? {
+            Some(line) => line
+                .trim_start_matches("seed ")
+                .parse()
+                .map_err(|_| parse_error(2, "invalid seed"))?,
+            None => return Err(parse_error(2, "missing seed")),
+        };
+
+        let mut frames = VecDeque::new();
+        for (i, line) in lines.enumerate() {
+            let line_number = i + 3;
+            let line = line?;
+            let mut fields = line.split(' ');
+            let mut coordinate = || -> Result<f64, ReplayError> {
+                fields
+                    .next()
+                    .and_then(|field| field.parse().ok())
+                    .ok_or_else(|| parse_error(line_number, "invalid mouse position"))
+            };
+            let mouse_pos = Vector2::new(coordinate()?, coordinate()?);
+            let keys = parse_codes(fields.next())
+                .ok_or_else(|| parse_error(line_number, "invalid key code"))?
+                .into_iter()
+                .map(Key::from)
+                .collect();
+            let mouse_buttons = parse_codes(fields.next())
+                .ok_or_else(|| parse_error(line_number, "invalid mouse button"))?
+                .into_iter()
+                .map(MouseButton::from)
+                .collect();
+            frames.push_back(InputFrame {
+                keys,
+                mouse_buttons,
+                mouse_pos,
+            });
+        }
+
+        Ok(InputReplay { seed, frames })
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Feeds the next recorded tick into `keyboard`. Returns `false` once the recording is exhausted.
+    pub fn apply(&mut self, keyboard: &mut KeyBoard) -> bool {
+        match self.frames.pop_front() {
+            Some(InputFrame {
+                keys,
+                mouse_buttons,
+                mouse_pos,
+            }) => {
+                keyboard.set_state(keys, mouse_buttons, mouse_pos);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("atoma-{}-{}.txt", name, std::process::id()))
+    }
+
+    fn replay(name: &str, text: &str) -> Result<InputReplay, ReplayError> {
+        let path = temp_path(name);
+        fs::write(&path, text).unwrap();
+        let replay = InputReplay::open(&path);
+        fs::remove_file(&path).unwrap();
+        replay
+    }
+
+    fn error_line(result: Result<InputReplay, ReplayError>) -> usize {
+        match result {
+            Err(ReplayError::Parse { line, .. }) => line,
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("malformed recording was accepted"),
+        }
+    }
+
+    #[test]
+    fn recordings_replay_keys_and_mouse_buttons() {
+        let path = temp_path("record");
+        let mut keyboard = KeyBoard::new();
+        let mut recorder = InputRecorder::create(&path, 42).unwrap();
+        keyboard.set_state(
+            [Key::Up, Key::E].iter().cloned().collect(),
+            [MouseButton::Left].iter().cloned().collect(),
+            (12., 34.).into(),
+        );
+        recorder.record(&keyboard).unwrap();
+        keyboard.clear();
+        recorder.record(&keyboard).unwrap();
+        drop(recorder);
+        assert!(fs::read_to_string(&path).unwrap().starts_with(HEADER));
+
+        let mut replay = InputReplay::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replay.seed(), 42);
+        let mut keyboard = KeyBoard::new();
+        assert!(replay.apply(&mut keyboard));
+        assert!(keyboard.up && keyboard.contains_key(&Key::E));
+        assert!(keyboard.is_pressed(MouseButton::Left));
+        assert_eq!(keyboard.mouse_pos, (12., 34.).into());
+        assert!(replay.apply(&mut keyboard));
+        assert!(keyboard.keys.is_empty() && keyboard.mouse_buttons.is_empty());
+        assert!(!replay.apply(&mut keyboard));
+    }
+
+    #[test]
+    fn malformed_lines_report_their_line_number() {
+        let header = format!("{}\nseed 7\n", HEADER);
+        assert_eq!(error_line(replay("header", "seed 7\n")), 1);
+        assert_eq!(error_line(replay("seed", &format!("{}\n", HEADER))), 2);
+        assert_eq!(error_line(replay("bad-seed", &format!("{}\nseed x\n", HEADER))), 2);
+        let coordinate = format!("{}0 0 \n1 y \n", header);
+        assert_eq!(error_line(replay("coordinate", &coordinate)), 4);
+        let key = format!("{}0 0 1,x\n", header);
+        assert_eq!(error_line(replay("key", &key)), 3);
+        let button = format!("{}0 0 1 -1\n", header);
+        assert_eq!(error_line(replay("button", &button)), 3);
+    }
+
+    #[test]
+    fn version_1_recordings_have_no_mouse_buttons() {
+        let up = u32::from(Key::Up);
+        let text = format!("atoma-input 1\nseed 7\n1 2 {}\n", up);
+        let mut replay = replay("version1", &text).unwrap();
+        let mut keyboard = KeyBoard::new();
+        assert!(replay.apply(&mut keyboard));
+        assert!(keyboard.up);
+        assert!(keyboard.mouse_buttons.is_empty());
+    }
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        assert_eq!(error_line(replay("version3", "atoma-input 3\nseed 7\n")), 1);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::entity::Direction;
+
+// Picks the look of a wall from the cells around it. Every one of the 8 neighbours of a
+// wall cell gets a bit, in one mask for the floor around it and one for the walls.
+const NORTH: u8 = 0b0000_0001;
+const NORTH_EAST: u8 = 0b0000_0010;
+const EAST: u8 = 0b0000_0100;
+const SOUTH_EAST: u8 = 0b0000_1000;
+const SOUTH: u8 = 0b0001_0000;
+const SOUTH_WEST: u8 = 0b0010_0000;
+const WEST: u8 = 0b0100_0000;
+const NORTH_WEST: u8 = 0b1000_0000;
+
+// Clockwise from north, in the order of the bits
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum WallShape {
+    // Straight wall with nothing behind it, the direction is the side of the room it
+    // belongs to, e.g. `UP` for a wall with floor below it
+    Wall(Direction),
+    // Side of a wall standing in the room, thicker than one cell
+    Edge(Direction),
+    // Concave corner of the floor, e.g. `(UP, LEFT)` in the top left corner of a room
+    InnerCorner(Direction, Direction),
+    // Convex corner of the wall, e.g. `(UP, LEFT)` with floor above and left of it
+    OuterCorner(Direction, Direction),
+    // Wall without any floor next to it
+    Fill,
+}
+
+/// Neighbour bits of the cell at `(x, y)`, set for the neighbours `matches` accepts.
+pub fn neighbour_mask<F: Fn(i32, i32) -> bool>(x: i32, y: i32, matches: F) -> u8 {
+    NEIGHBOURS
+        .iter()
+        .enumerate()
+        .filter(|(_, (dx, dy))| matches(x + dx, y + dy))
+        .fold(0, |mask, (bit, _)| mask | 1 << bit)
+}
+
+/// Shape of a wall with floor on the neighbours in `floor` and walls on the ones in `walls`.
+pub fn wall_shape(floor: u8, walls: u8) -> WallShape {
+    let open = |bits: u8| floor & bits == bits;
+    // The cell behind a wall facing `side` decides between a wall and an edge
+    let straight = |side: Direction, behind: u8| {
+        if walls & behind != 0 {
+            WallShape::Edge(side)
+        } else {
+            WallShape::Wall(side)
+        }
+    };
+    match (open(NORTH), open(EAST), open(SOUTH), open(WEST)) {
+        (false, false, false, false) => {
+            // Only diagonal floor, the inside of a room corner
+            if open(SOUTH_EAST) {
+                WallShape::InnerCorner(Direction::UP, Direction::LEFT)
+            } else if open(SOUTH_WEST) {
+                WallShape::InnerCorner(Direction::UP, Direction::RIGHT)
+            } else if open(NORTH_EAST) {
+                WallShape::InnerCorner(Direction::DOWN, Direction::LEFT)
+            } else if open(NORTH_WEST) {
+                WallShape::InnerCorner(Direction::DOWN, Direction::RIGHT)
+            } else {
+                WallShape::Fill
+            }
+        }
+        (true, false, false, true) => WallShape::OuterCorner(Direction::UP, Direction::LEFT),
+        (true, true, false, false) => WallShape::OuterCorner(Direction::UP, Direction::RIGHT),
+        (false, false, true, true) => WallShape::OuterCorner(Direction::DOWN, Direction::LEFT),
+        (false, true, true, false) => WallShape::OuterCorner(Direction::DOWN, Direction::RIGHT),
+        // Walls seen from below win, they are the ones facing the player
+        (_, _, true, _) => straight(Direction::UP, NORTH),
+        (true, _, false, _) => straight(Direction::DOWN, SOUTH),
+        (false, true, false, _) => straight(Direction::LEFT, WEST),
+        (false, false, false, true) => straight(Direction::RIGHT, EAST),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Shape of the middle of a 3x3 neighbourhood, `.` is floor, `#` wall and anything
+    // else lies outside of the room
+    fn shape(rows: [&str; 3]) -> WallShape {
+        let cell = |x: i32, y: i32| rows[y as usize].as_bytes()[x as usize];
+        let floor = neighbour_mask(1, 1, |x, y| cell(x, y) == b'.');
+        let walls = neighbour_mask(1, 1, |x, y| cell(x, y) == b'#');
+        wall_shape(floor, walls)
+    }
+
+    #[test]
+    fn neighbour_bits_go_clockwise_from_north() {
+        let only = |nx: i32, ny: i32| neighbour_mask(0, 0, |x, y| (x, y) == (nx, ny));
+        assert_eq!(only(0, -1), NORTH);
+        assert_eq!(only(1, -1), NORTH_EAST);
+        assert_eq!(only(1, 1), SOUTH_EAST);
+        assert_eq!(only(-1, 0), WEST);
+        assert_eq!(only(-1, -1), NORTH_WEST);
+        assert_eq!(only(0, 0), 0);
+        assert_eq!(neighbour_mask(5, 5, |_, _| true), 0xff);
+    }
+
+    #[test]
+    fn outer_corners() {
+        let (up, down, left, right) = (
+            Direction::UP,
+            Direction::DOWN,
+            Direction::LEFT,
+            Direction::RIGHT,
+        );
+        let cases = [
+            (["...", ".##", ".##"], WallShape::OuterCorner(up, left)),
+            (["...", "##.", "##."], WallShape::OuterCorner(up, right)),
+            ([".##", ".##", "..."], WallShape::OuterCorner(down, left)),
+            (["##.", "##.", "..."], WallShape::OuterCorner(down, right)),
+        ];
+        for (rows, expected) in cases.iter() {
+            assert_eq!(shape(*rows), *expected, "{:?}", rows);
+        }
+    }
+
+    #[test]
+    fn inner_corners() {
+        let (up, down, left, right) = (
+            Direction::UP,
+            Direction::DOWN,
+            Direction::LEFT,
+            Direction::RIGHT,
+        );
+        let cases = [
+            (["   ", " ##", " #."], WallShape::InnerCorner(up, left)),
+            (["   ", "## ", ".# "], WallShape::InnerCorner(up, right)),
+            ([" #.", " ##", "   "], WallShape::InnerCorner(down, left)),
+            ([".# ", "## ", "   "], WallShape::InnerCorner(down, right)),
+            // Floor on several diagonals, the corner facing the player wins
+            ([".#.", "###", ".#."], WallShape::InnerCorner(up, left)),
+            (["##.", "###", ".##"], WallShape::InnerCorner(up, right)),
+        ];
+        for (rows, expected) in cases.iter() {
+            assert_eq!(shape(*rows), *expected, "{:?}", rows);
+        }
+    }
+
+    #[test]
+    fn straight_walls_and_edges() {
+        let (up, down, left, right) = (
+            Direction::UP,
+            Direction::DOWN,
+            Direction::LEFT,
+            Direction::RIGHT,
+        );
+        let cases = [
+            // Room walls, with nothing behind them
+            (["   ", "###", "..."], WallShape::Wall(up)),
+            (["...", "###", "   "], WallShape::Wall(down)),
+            ([" #.", " #.", " #."], WallShape::Wall(left)),
+            ([".# ", ".# ", ".# "], WallShape::Wall(right)),
+            // Sides of thick walls standing in the room
+            (["###", "###", "..."], WallShape::Edge(up)),
+            (["...", "###", "###"], WallShape::Edge(down)),
+            (["##.", "##.", "##."], WallShape::Edge(left)),
+            ([".##", ".##", ".##"], WallShape::Edge(right)),
+        ];
+        for (rows, expected) in cases.iter() {
+            assert_eq!(shape(*rows), *expected, "{:?}", rows);
+        }
+    }
+
+    #[test]
+    fn thin_walls_show_the_side_facing_the_player() {
+        let cases = [
+            (["...", "###", "..."], WallShape::Wall(Direction::UP)),
+            ([".#.", ".#.", ".#."], WallShape::Wall(Direction::LEFT)),
+            // A lone pillar, and the lower end of a thin wall, which goes on behind it
+            (["...", ".#.", "..."], WallShape::Wall(Direction::UP)),
+            ([".#.", ".#.", "..."], WallShape::Edge(Direction::UP)),
+        ];
+        for (rows, expected) in cases.iter() {
+            assert_eq!(shape(*rows), *expected, "{:?}", rows);
+        }
+    }
+
+    #[test]
+    fn walls_without_floor_are_filled() {
+        assert_eq!(shape(["###", "###", "###"]), WallShape::Fill);
+        assert_eq!(shape(["   ", " # ", "   "]), WallShape::Fill);
+        assert_eq!(shape(["## ", "###", " ##"]), WallShape::Fill);
+    }
+}
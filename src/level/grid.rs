@@ -0,0 +1,51 @@
+use cgmath::Vector2;
+
+// 2D grid with runtime dimensions addressed by signed cell positions, stored row by
+// row. Positions outside of the grid are simply empty.
+#[derive(Clone, Debug)]
+pub struct Grid<T> {
+    dimensions: Vector2<i32>,
+    cells: Vec<Option<T>>,
+}
+
+impl<T> Grid<T> {
+    pub fn new(dimensions: Vector2<i32>) -> Self {
+        let mut cells = Vec::new();
+        cells.resize_with((dimensions.x.max(0) * dimensions.y.max(0)) as usize, || None);
+        Grid { dimensions, cells }
+    }
+
+    pub fn dimensions(&self) -> Vector2<i32> {
+        self.dimensions
+    }
+
+    pub fn contains(&self, pos: Vector2<i32>) -> bool {
+        pos.x >= 0 && pos.x < self.dimensions.x && pos.y >= 0 && pos.y < self.dimensions.y
+    }
+
+    fn index(&self, pos: Vector2<i32>) -> Option<usize> {
+        if self.contains(pos) {
+            Some((pos.x + pos.y * self.dimensions.x) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, pos: Vector2<i32>) -> Option<&T> {
+        self.index(pos).and_then(|i| self.cells[i].as_ref())
+    }
+
+    // Panics when `pos` is outside of the grid
+    pub fn set(&mut self, pos: Vector2<i32>, value: T) {
+        let i = self.index(pos).expect("Grid position out of bounds");
+        self.cells[i] = Some(value);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Vector2<i32>, &T)> {
+        let width = self.dimensions.x;
+        self.cells.iter().enumerate().filter_map(move |(i, cell)| {
+            cell.as_ref()
+                .map(|value| (Vector2::new(i as i32 % width, i as i32 / width), value))
+        })
+    }
+}
@@ -1,23 +1,148 @@
+pub mod autotile;
+pub mod grid;
+pub mod navigation;
 pub mod room;
+pub mod spawn;
+pub mod template;
+pub mod tiled;
 pub mod tile;
 
 use cgmath::Vector2;
-use rand::{thread_rng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::graphics::screen::Screen;
-use crate::level::room::*;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use crate::level::{
+    grid::Grid,
+    room::*,
+    template::{TemplateError, TemplateRegistry},
+};
+use std::collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum LevelError {
+    Template(TemplateError),
+    // More rooms were requested than cells in the grid or room ids
+    TooManyRooms { requested: usize, capacity: usize },
+    // The layout got stuck before reaching the requested number of rooms
+    RoomsDoNotFit { requested: usize, placed: usize },
+    // No layout with enough loops or dead ends was found
+    TooFewLoops { requested: usize, made: usize },
+    TooFewDeadEnds { requested: usize, made: usize },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Template(err) => write!(f, "{}", err),
+            LevelError::TooManyRooms {
+                requested,
+                capacity,
+            } => write!(
+                f,
+                "{} rooms requested, but only {} can exist",
+                requested, capacity
+            ),
+            LevelError::RoomsDoNotFit { requested, placed } => write!(
+                f,
+                "only {} of {} rooms fit into the grid",
+                placed, requested
+            ),
+            LevelError::TooFewLoops { requested, made } => {
+                write!(f, "only {} of {} loops could be made", made, requested)
+            }
+            LevelError::TooFewDeadEnds { requested, made } => {
+                write!(f, "only {} of {} dead ends could be made", made, requested)
+            }
+        }
+    }
+}
+
+impl From<TemplateError> for LevelError {
+    fn from(err: TemplateError) -> Self {
+        LevelError::Template(err)
+    }
+}
+
+// Per floor generation and difficulty parameters
+#[derive(Debug, Copy, Clone)]
+pub struct FloorConfig {
+    pub grid_size: Vector2<i32>,
+    pub room_count: usize,
+    // Spawn table points spent on the enemies of a normal room, boss rooms get a few more
+    pub enemy_budget: u32,
+    pub tileset: usize,
+    pub topology: Topology,
+}
+
+// Shape of the graph of rooms and doors of a floor
+#[derive(Debug, Copy, Clone)]
+pub struct Topology {
+    // Doors added on top of the ones that make the floor a tree, each closes a loop
+    pub loops: usize,
+    // Rooms besides the start with a single door, the boss, treasure and shop take three
+    pub min_dead_ends: usize,
+    // Most doors between the start room and any other room
+    pub max_depth: usize,
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Topology {
+            loops: 1,
+            min_dead_ends: 3,
+            max_depth: 6,
+        }
+    }
+}
+
+impl FloorConfig {
+    /// Floors get larger and more crowded the deeper the player gets. `depth` starts at 1.
+    pub fn for_depth(depth: u32) -> Self {
+        let level = depth.max(1) as usize - 1;
+        let grid = 9 + level.min(6) as i32;
+        FloorConfig {
+            grid_size: (grid, grid).into(),
+            room_count: (16 + level * 4).min((grid * grid / 2) as usize),
+            enemy_budget: (2 + level).min(6) as u32,
+            tileset: level,
+            topology: Topology {
+                loops: (1 + level / 2).min(4),
+                min_dead_ends: 3 + level.min(6) / 3,
+                max_depth: 6 + level.min(6),
+            },
+        }
+    }
 
-pub const MAP_GRID_SIZE: usize = 9;
-pub const MAP_GRID_SIZE_MINUS_ONE: usize = 8;
+    // Only normal and boss rooms are hostile, the rest are safe to explore
+    pub fn enemy_budget(&self, room_type: RoomType) -> u32 {
+        match room_type {
+            RoomType::Normal => self.enemy_budget,
+            RoomType::Boss => self.enemy_budget + 2,
+            _ => 0,
+        }
+    }
+}
+
+// Layouts missing the topology are thrown away and generated again, up to this often
+const LAYOUT_ATTEMPTS: usize = 32;
 
 struct LevelBuilder {
-    rooms: [[Option<RoomBuilder>; MAP_GRID_SIZE]; MAP_GRID_SIZE],
-    taken_positions: HashMap<Vector2<i32>, RoomId>,
+    // Room of every occupied grid cell, rooms may cover several cells
+    cells: Grid<RoomId>,
+    rooms: BTreeMap<RoomId, RoomBuilder>,
+    // Pairs of rooms with a door between them, the smaller id first
+    connections: BTreeSet<(RoomId, RoomId)>,
+    // Doors between the start and each room, while the layout is still a tree
+    depths: HashMap<RoomId, usize>,
     possible_positions: Vec<Vector2<i32>>,
     number_of_rooms: usize,
-    next_id: u8,
+    secret_rooms: usize,
+    start_pos: Vector2<i32>,
+    tileset: usize,
+    topology: Topology,
+    next_id: RoomId,
+    rng: StdRng,
 }
 
 impl LevelBuilder {
@@ -27,31 +152,31 @@ impl LevelBuilder {
     //If the selected element has a room next to it, make a room there! (fill the array element with not zero,
     //I think different rooms will have different numbers, 0 will be the starter room where the player will spawn,
     //1 will be one room type, 2 will be an other,...)
-    //If there is no room next to the selected element, do step 3 again. We shouldn't make room
-    //if the selected empty place has 2 or more neighbours! This will make the level a little bit different (figure 1, 2nd image)
+    //If there is no room next to the selected element, do step 3 again.
     //Do it until we want: if we want 15 room we need to do step 3 until we have 15 rooms
     //(don't forget, we already have a starter room!)
 
-    pub fn new() -> LevelBuilder {
-        let start_pos = Vector2::new(MAP_GRID_SIZE as i32 / 2, MAP_GRID_SIZE as i32 / 2);
-        let mut builder = LevelBuilder {
-            rooms: Default::default(),
-            taken_positions: std::iter::once((start_pos, 0)).collect(),
+    pub fn new(seed: u64) -> LevelBuilder {
+        LevelBuilder {
+            cells: Grid::new((9, 9).into()),
+            rooms: BTreeMap::new(),
+            connections: BTreeSet::new(),
+            depths: HashMap::new(),
             possible_positions: vec![],
             number_of_rooms: 15,
+            secret_rooms: 1,
+            start_pos: (4, 4).into(),
+            tileset: 0,
+            topology: Default::default(),
             next_id: 1,
-        };
-
-        let start_room = RoomBuilder::new()
-            .with_grid_pos(start_pos)
-            .with_room_type(RoomType::Start)
-            .with_path(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("res/sprites/room.png"))
-            .with_id(0);
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
 
-        builder.rooms[start_pos.x as usize][start_pos.y as usize] = Some(start_room);
-        let ref free_positions = builder.free_neighbour_positions(start_pos);
-        builder.possible_positions.extend_from_slice(free_positions);
-        builder
+    pub fn with_grid_size(mut self, grid_size: Vector2<i32>) -> Self {
+        self.cells = Grid::new(grid_size);
+        self.start_pos = grid_size / 2;
+        self
     }
 
     pub fn with_number_of_rooms(mut self, number_of_rooms: usize) -> Self {
@@ -59,163 +184,433 @@ impl LevelBuilder {
         self
     }
 
-    fn free_neighbour_positions(&self, pos: Vector2<i32>) -> Vec<Vector2<i32>> {
-        let mut free_positions = Vec::new();
-        for x in [pos.x - 1, pos.x + 1].into_iter() {
-            if *x < 0 || *x > MAP_GRID_SIZE_MINUS_ONE as i32 {
-                continue;
-            }
-            let y = pos.y;
-            if let None = self.rooms[*x as usize][y as usize] {
-                free_positions.push(Vector2::new(*x, y));
-            }
+    pub fn with_tileset(mut self, tileset: usize) -> Self {
+        self.tileset = tileset;
+        self
+    }
+
+    pub fn with_topology(mut self, topology: Topology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    fn add_room(&mut self, room: RoomBuilder, pos: Vector2<i32>) {
+        let room = room.with_grid_pos(pos);
+        let cells: Vec<Vector2<i32>> = room.cells().collect();
+        for cell in cells.iter() {
+            self.cells.set(*cell, room.id);
         }
-        for y in [pos.y - 1, pos.y + 1].into_iter() {
-            if *y < 0 || *y > MAP_GRID_SIZE_MINUS_ONE as i32 {
-                continue;
-            }
-            let x = pos.x;
-            if let None = self.rooms[x as usize][*y as usize] {
-                free_positions.push(Vector2::new(x, *y));
+        self.rooms.insert(room.id, room);
+        for cell in cells {
+            for free_pos in self.free_neighbour_positions(cell) {
+                if !self.possible_positions.contains(&free_pos) {
+                    self.possible_positions.push(free_pos);
+                }
             }
         }
-        free_positions
     }
 
-    pub fn build(mut self) -> Level {
-        self.create_rooms();
-        self.set_room_doors();
+    fn free_neighbour_positions(&self, pos: Vector2<i32>) -> Vec<Vector2<i32>> {
+        neighbour_positions(pos)
+            .iter()
+            .filter(|pos| self.cells.contains(**pos) && self.cells.get(**pos).is_none())
+            .cloned()
+            .collect()
+    }
 
-        let mut map_grid = [[false; MAP_GRID_SIZE]; MAP_GRID_SIZE];
-        let mut rooms = HashMap::new();
+    fn connect(&mut self, a: RoomId, b: RoomId) {
+        self.connections.insert((a.min(b), a.max(b)));
+    }
 
-        for x in 0..MAP_GRID_SIZE {
-            for y in 0..MAP_GRID_SIZE {
-                if let Some(room) = std::mem::replace(&mut self.rooms[x][y], None) {
-                    map_grid[x][y] = true;
-                    let (id, room) = room.build();
-                    rooms.insert(id, room);
-                }
+    fn is_connected(&self, a: RoomId, b: RoomId) -> bool {
+        self.connections.contains(&(a.min(b), a.max(b)))
+    }
+
+    fn connected_rooms(&self, id: RoomId) -> Vec<RoomId> {
+        self.connections
+            .iter()
+            .filter_map(|&(a, b)| match id {
+                _ if a == id => Some(b),
+                _ if b == id => Some(a),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn is_dead_end(&self, id: RoomId) -> bool {
+        id != 0 && self.connected_rooms(id).len() == 1
+    }
+
+    pub fn build(
+        mut self,
+        templates: &TemplateRegistry,
+        config: FloorConfig,
+    ) -> Result<Level, LevelError> {
+        let dimensions = self.cells.dimensions();
+        let capacity = ((dimensions.x.max(0) * dimensions.y.max(0)) as usize)
+            .min(RoomId::MAX as usize + 1);
+        let requested = self.number_of_rooms + self.secret_rooms;
+        if self.number_of_rooms == 0 || requested > capacity {
+            return Err(LevelError::TooManyRooms {
+                requested,
+                capacity,
+            });
+        }
+
+        let mut attempts = 1;
+        while let Err(err) = self.create_layout(templates) {
+            if attempts == LAYOUT_ATTEMPTS {
+                return Err(err);
             }
+            attempts += 1;
+        }
+        self.assign_room_types(templates);
+        self.set_room_doors();
+
+        let mut rooms = HashMap::new();
+        for (_, room) in std::mem::take(&mut self.rooms) {
+            let template = templates.choose(room.room_type(), room.shape(), &mut self.rng)?;
+            let (id, room) = room
+                .with_tileset(self.tileset)
+                .build(template, &mut self.rng)?;
+            rooms.insert(id, room);
         }
 
-        Level {
-            map_grid,
+        Ok(Level {
+            map_grid: self.cells,
             rooms,
             current: 0,
+            config,
+        })
+    }
+
+    // A tree of rooms grown from the start room, with a few loops added on top
+    fn create_layout(&mut self, templates: &TemplateRegistry) -> Result<(), LevelError> {
+        self.cells = Grid::new(self.cells.dimensions());
+        self.rooms.clear();
+        self.connections.clear();
+        self.depths.clear();
+        self.possible_positions.clear();
+        self.next_id = 1;
+
+        let start_room = RoomBuilder::new()
+            .with_room_type(RoomType::Start)
+            .with_id(0);
+        let start_pos = self.start_pos;
+        self.add_room(start_room, start_pos);
+        self.depths.insert(0, 0);
+
+        self.create_rooms(templates)?;
+        self.add_loops()?;
+
+        let dead_ends = self.rooms.keys().filter(|id| self.is_dead_end(**id)).count();
+        if dead_ends < self.topology.min_dead_ends {
+            return Err(LevelError::TooFewDeadEnds {
+                requested: self.topology.min_dead_ends,
+                made: dead_ends,
+            });
         }
+        Ok(())
     }
 
-    fn create_rooms(&mut self) {
-        let mut rng = thread_rng();
-        let mut new_room_index = 0;
-        let mut new_pos = Vector2::new(0, 0);
-        for _ in 1..self.number_of_rooms {
-            //let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("res/sprites/room{}.png", rng.gen_range(1, 4)));
-            let path =
-                PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("res/sprites/room.png"));
-            for _ in 0..400 {
-                new_room_index = rng.gen_range(0, self.possible_positions.len());
-                new_pos = self.possible_positions[new_room_index];
-                if self.neighbour_count(new_pos) < 2 {
-                    break;
-                }
+    // Grows the layout one room at a time, every new room gets a door to one room next
+    // to it that isn't at the maximum depth yet. Its shape is picked like its template
+    // will be, rooms that don't fit at the chosen position cover a single cell instead.
+    fn create_rooms(&mut self, templates: &TemplateRegistry) -> Result<(), LevelError> {
+        for placed in 1..self.number_of_rooms {
+            let cells = &self.cells;
+            self.possible_positions
+                .retain(|pos| cells.get(*pos).is_none());
+            let candidates: Vec<(Vector2<i32>, Vec<RoomId>)> = self
+                .possible_positions
+                .iter()
+                .map(|pos| {
+                    let parents = self
+                        .touching_rooms(&[*pos])
+                        .into_iter()
+                        .filter(|id| self.depths[id] < self.topology.max_depth)
+                        .collect::<Vec<_>>();
+                    (*pos, parents)
+                })
+                .filter(|(_, parents)| !parents.is_empty())
+                .collect();
+            if candidates.is_empty() {
+                return Err(LevelError::RoomsDoNotFit {
+                    requested: self.number_of_rooms,
+                    placed,
+                });
             }
-
-            // add new room to rooms
+            let (new_pos, parents) = &candidates[self.rng.gen_range(0, candidates.len())];
+            let parent = parents[self.rng.gen_range(0, parents.len())];
+
+            let shape = templates
+                .choose_shape(RoomType::Normal, &mut self.rng)
+                .cloned()
+                .unwrap_or_default();
+            let (shape, anchor) = match self.fit_shape(&shape, *new_pos) {
+                Some(anchor) => (shape, anchor),
+                None => (RoomShape::single(), *new_pos),
+            };
             let id = self.next_id;
-            let room = RoomBuilder::new()
-                .with_path(&path)
-                .with_grid_pos(new_pos.into())
-                .with_id(id);
             self.next_id += 1;
-            self.rooms[new_pos.x as usize][new_pos.y as usize] = Some(room);
+            self.add_room(RoomBuilder::new().with_id(id).with_shape(shape), anchor);
+            self.connect(parent, id);
+            self.depths.insert(id, self.depths[&parent] + 1);
+        }
+        Ok(())
+    }
 
-            // update taken positions
-            self.taken_positions.insert(new_pos, id);
+    // Top left corner of a placement of `shape` covering `pos` on free cells
+    fn fit_shape(&self, shape: &RoomShape, pos: Vector2<i32>) -> Option<Vector2<i32>> {
+        shape.cells().iter().map(|cell| pos - cell).find(|anchor| {
+            shape.cells().iter().all(|cell| {
+                let cell = anchor + cell;
+                self.cells.contains(cell) && self.cells.get(cell).is_none()
+            })
+        })
+    }
 
-            // update number of rooms
-            self.number_of_rooms += 1;
+    // Connects neighbouring rooms that don't have a door between them yet. Doors into
+    // dead ends are left out, so the special rooms keep their single entrance.
+    fn add_loops(&mut self) -> Result<(), LevelError> {
+        let mut candidates = Vec::new();
+        for id in self.rooms.keys() {
+            for other in self.room_neighbours(*id) {
+                if *id < other && !self.is_connected(*id, other) {
+                    candidates.push((*id, other));
+                }
+            }
+        }
+        let mut made = 0;
+        while made < self.topology.loops && !candidates.is_empty() {
+            let (a, b) = candidates.swap_remove(self.rng.gen_range(0, candidates.len()));
+            if !self.is_dead_end(a) && !self.is_dead_end(b) {
+                self.connect(a, b);
+                made += 1;
+            }
+        }
+        if made < self.topology.loops {
+            return Err(LevelError::TooFewLoops {
+                requested: self.topology.loops,
+                made,
+            });
+        }
+        Ok(())
+    }
 
-            // update possible positions
-            self.possible_positions.remove(new_room_index);
-            let ref free_positions = self.free_neighbour_positions(new_pos);
-            self.possible_positions.extend_from_slice(free_positions);
+    // Rooms next to any of `cells`, not counting rooms covering them
+    fn touching_rooms(&self, cells: &[Vector2<i32>]) -> BTreeSet<RoomId> {
+        let mut rooms = BTreeSet::new();
+        for cell in cells {
+            for next in neighbour_positions(*cell).iter() {
+                if let Some(id) = self.cells.get(*next) {
+                    if self.cells.get(*cell) != Some(id) {
+                        rooms.insert(*id);
+                    }
+                }
+            }
+        }
+        rooms
+    }
 
-            if self.taken_positions.len() == self.number_of_rooms {
-                break;
+    fn room_neighbours(&self, id: RoomId) -> BTreeSet<RoomId> {
+        let cells: Vec<Vector2<i32>> = self.rooms[&id].cells().collect();
+        self.touching_rooms(&cells)
+    }
+
+    fn set_room_type(&mut self, id: RoomId, room_type: RoomType) {
+        if let Some(room) = self.rooms.remove(&id) {
+            self.rooms.insert(id, room.with_room_type(room_type));
+        }
+    }
+
+    fn room_type_at(&self, pos: Vector2<i32>) -> Option<RoomType> {
+        self.cells
+            .get(pos)
+            .map(|id| self.rooms[id].room_type())
+    }
+
+    // Number of doors between the start room and every other room
+    fn distances_from_start(&self) -> HashMap<RoomId, usize> {
+        let mut distances: HashMap<_, _> = std::iter::once((0, 0)).collect();
+        let mut queue = VecDeque::new();
+        queue.push_back(0);
+        while let Some(id) = queue.pop_front() {
+            let distance = distances[&id];
+            for next in self.connected_rooms(id) {
+                if let Entry::Vacant(entry) = distances.entry(next) {
+                    entry.insert(distance + 1);
+                    queue.push_back(next);
+                }
             }
         }
+        distances
     }
 
-    fn neighbour_count(&self, pos: Vector2<i32>) -> usize {
-        let mut neighbours = 0;
-        if self
-            .taken_positions
-            .contains_key(&(pos.x + 1, pos.y).into())
-        {
-            neighbours += 1;
+    // The boss waits in the dead end farthest from the start, the treasure room and the
+    // shop take random other dead ends. Only dead ends with a template of the right shape
+    // qualify. Secret rooms are squeezed into empty cells surrounded by several rooms.
+    fn assign_room_types(&mut self, templates: &TemplateRegistry) {
+        let distances = self.distances_from_start();
+        let mut dead_ends: Vec<(usize, RoomId)> = self
+            .rooms
+            .keys()
+            .filter(|id| self.is_dead_end(**id))
+            .map(|id| (distances[id], *id))
+            .collect();
+        dead_ends.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        for room_type in [RoomType::Boss, RoomType::Treasure, RoomType::Shop].iter() {
+            let fitting: Vec<usize> = (0..dead_ends.len())
+                .filter(|i| templates.has_shape(*room_type, self.rooms[&dead_ends[*i].1].shape()))
+                .collect();
+            if fitting.is_empty() {
+                continue;
+            }
+            // The farthest one for the boss
+            let index = if *room_type == RoomType::Boss {
+                fitting[0]
+            } else {
+                fitting[self.rng.gen_range(0, fitting.len())]
+            };
+            let (_, id) = dead_ends.remove(index);
+            self.set_room_type(id, *room_type);
         }
-        if self
-            .taken_positions
-            .contains_key(&(pos.x - 1, pos.y).into())
-        {
-            neighbours += 1;
+
+        for _ in 0..self.secret_rooms {
+            self.place_secret_room();
         }
-        if self
-            .taken_positions
-            .contains_key(&(pos.x, pos.y + 1).into())
-        {
-            neighbours += 1;
+    }
+
+    // Secret rooms get a door to every room around them, except to dead ends, which have
+    // to stay dead ends. One of those rooms has to be closer than the maximum depth.
+    fn place_secret_room(&mut self) {
+        let distances = self.distances_from_start();
+        let mut candidates = Vec::new();
+        let dimensions = self.cells.dimensions();
+        for y in 0..dimensions.y {
+            for x in 0..dimensions.x {
+                let pos = Vector2::new(x, y);
+                if self.cells.get(pos).is_some() {
+                    continue;
+                }
+                // Special rooms have to stay dead ends
+                let next_to_special = neighbour_positions(pos).iter().any(|next| {
+                    !matches!(
+                        self.room_type_at(*next),
+                        Some(RoomType::Start) | Some(RoomType::Normal) | None
+                    )
+                });
+                let neighbours = self.secret_room_neighbours(pos);
+                let too_deep = neighbours
+                    .iter()
+                    .all(|id| distances[id] >= self.topology.max_depth);
+                if neighbours.len() >= 2 && !next_to_special && !too_deep {
+                    candidates.push((neighbours.len(), pos));
+                }
+            }
         }
-        if self
-            .taken_positions
-            .contains_key(&(pos.x, pos.y - 1).into())
-        {
-            neighbours += 1;
+        let most_neighbours = match candidates.iter().map(|(n, _)| *n).max() {
+            Some(n) => n,
+            None => return,
+        };
+        candidates.retain(|(n, _)| *n == most_neighbours);
+        let (_, pos) = candidates[self.rng.gen_range(0, candidates.len())];
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let room = RoomBuilder::new()
+            .with_room_type(RoomType::Secret)
+            .with_id(id);
+        let neighbours = self.secret_room_neighbours(pos);
+        self.add_room(room, pos);
+        for other in neighbours {
+            self.connect(id, other);
         }
-        neighbours
     }
 
+    fn secret_room_neighbours(&self, pos: Vector2<i32>) -> Vec<RoomId> {
+        self.touching_rooms(&[pos])
+            .into_iter()
+            .filter(|id| !self.is_dead_end(*id))
+            .collect()
+    }
+
+    // One door for every connection, on a random edge shared by the two rooms
     fn set_room_doors(&mut self) {
-        for x in 0..MAP_GRID_SIZE {
-            for y in 0..MAP_GRID_SIZE {
-                if let Some(ref mut room) = self.rooms[x][y] {
-                    let xi = x as i32;
-                    let yi = y as i32;
-                    if let Some(id) = self.taken_positions.get(&(xi - 1, yi).into()) {
-                        room.add_neighbour(Neighbour::West(*id));
-                    }
-                    if let Some(id) = self.taken_positions.get(&(xi + 1, yi).into()) {
-                        room.add_neighbour(Neighbour::East(*id));
-                    }
-                    if let Some(id) = self.taken_positions.get(&(xi, yi - 1).into()) {
-                        room.add_neighbour(Neighbour::North(*id));
-                    }
-                    if let Some(id) = self.taken_positions.get(&(xi, yi + 1).into()) {
-                        room.add_neighbour(Neighbour::South(*id));
-                    }
-                }
+        let connections: Vec<(RoomId, RoomId)> = self.connections.iter().cloned().collect();
+        for (a, b) in connections {
+            let edges: Vec<(Vector2<i32>, Side)> = self.rooms[&a]
+                .cells()
+                .flat_map(|cell| Side::ALL.iter().map(move |side| (cell, *side)))
+                .filter(|(cell, side)| self.cells.get(cell + side.offset()) == Some(&b))
+                .collect();
+            if edges.is_empty() {
+                continue;
+            }
+            let (cell, side) = edges[self.rng.gen_range(0, edges.len())];
+            let doors = [(a, cell, side, b), (b, cell + side.offset(), side.opposite(), a)];
+            for (id, cell, side, other) in doors.iter() {
+                let room = self.rooms.get_mut(id).unwrap();
+                let neighbour = Neighbour {
+                    cell: cell - room.grid_pos(),
+                    side: *side,
+                    room: *other,
+                };
+                room.add_neighbour(neighbour);
             }
         }
     }
 }
 
+fn neighbour_positions(pos: Vector2<i32>) -> [Vector2<i32>; 4] {
+    [
+        (pos.x + 1, pos.y).into(),
+        (pos.x - 1, pos.y).into(),
+        (pos.x, pos.y + 1).into(),
+        (pos.x, pos.y - 1).into(),
+    ]
+}
+
 pub struct Level {
-    pub map_grid: [[bool; MAP_GRID_SIZE]; MAP_GRID_SIZE],
+    pub map_grid: Grid<RoomId>,
     rooms: HashMap<RoomId, Room>,
     current: RoomId,
+    config: FloorConfig,
 }
 
 impl Level {
-    pub fn new(room_count: usize) -> Self {
-        LevelBuilder::new().with_number_of_rooms(room_count).build()
+    /// Generates the floor described by `config`. The same `seed` and templates always
+    /// yield the same layout.
+    pub fn new(
+        templates: &TemplateRegistry,
+        config: FloorConfig,
+        seed: u64,
+    ) -> Result<Self, LevelError> {
+        LevelBuilder::new(seed)
+            .with_grid_size(config.grid_size)
+            .with_number_of_rooms(config.room_count)
+            .with_tileset(config.tileset)
+            .with_topology(config.topology)
+            .build(templates, config)
+    }
+
+    pub fn config(&self) -> &FloorConfig {
+        &self.config
     }
 
     pub fn current_room(&self) -> &Room {
         &self.rooms[&self.current]
     }
 
+    pub fn room(&self, id: RoomId) -> Option<&Room> {
+        self.rooms.get(&id)
+    }
+
+    pub fn current_room_mut(&mut self) -> &mut Room {
+        self.rooms.get_mut(&self.current).unwrap()
+    }
+
     pub fn current_room_id(&self) -> RoomId {
         self.current
     }
@@ -234,15 +629,280 @@ impl Level {
         self.current_room().dimensions()
     }
 
-    pub fn map_info(&self) -> MapInfo<'_> {
+    // Only visited rooms and the rooms behind their doors are on the map, secret
+    // rooms stay hidden until they are found
+    pub fn map_info(&self) -> MapInfo {
+        let mut rooms = Vec::new();
+        for (grid_pos, id) in self.map_grid.iter() {
+            let room = &self.rooms[id];
+            let fog = if *id == self.current {
+                MapFog::Current
+            } else if room.visited {
+                MapFog::Visited
+            } else if room.room_type != RoomType::Secret && self.is_behind_visited_door(*id) {
+                MapFog::Seen
+            } else {
+                continue;
+            };
+            let joined = Side::ALL
+                .iter()
+                .filter(|side| self.map_grid.get(grid_pos + side.offset()) == Some(id))
+                .cloned()
+                .collect();
+            rooms.push(MapRoom {
+                grid_pos,
+                room_type: room.room_type,
+                fog,
+                joined,
+            });
+        }
         MapInfo {
-            map_grid: &self.map_grid,
+            dimensions: self.map_grid.dimensions(),
             current_grid_pos: self.rooms[&self.current].grid_pos,
+            rooms,
         }
     }
+
+    fn is_behind_visited_door(&self, id: RoomId) -> bool {
+        self.rooms[&id]
+            .load_info
+            .doors
+            .iter()
+            .any(|door| self.rooms[&door.room].visited)
+    }
 }
 
-pub struct MapInfo<'a> {
-    pub map_grid: &'a [[bool; MAP_GRID_SIZE]; MAP_GRID_SIZE],
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum MapFog {
+    Current,
+    Visited,
+    // Not visited yet, but next to a visited room
+    Seen,
+}
+
+#[derive(Debug, Clone)]
+pub struct MapRoom {
+    pub grid_pos: Vector2<i32>,
+    pub room_type: RoomType,
+    pub fog: MapFog,
+    // Sides on which the room continues into the next cell
+    pub joined: Vec<Side>,
+}
+
+pub struct MapInfo {
+    pub dimensions: Vector2<i32>,
     pub current_grid_pos: Vector2<i32>,
+    pub rooms: Vec<MapRoom>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn templates() -> TemplateRegistry {
+        TemplateRegistry::load(&TemplateRegistry::default_dir()).unwrap()
+    }
+
+    fn config(grid_size: (i32, i32), room_count: usize, topology: Topology) -> FloorConfig {
+        FloorConfig {
+            grid_size: grid_size.into(),
+            room_count,
+            topology,
+            ..FloorConfig::for_depth(1)
+        }
+    }
+
+    // No loops, dead ends or depth limit to get in the way
+    fn any_topology() -> Topology {
+        Topology {
+            loops: 0,
+            min_dead_ends: 0,
+            max_depth: usize::MAX,
+        }
+    }
+
+    const SEEDS: u64 = 40;
+    const DEPTHS: u32 = 7;
+
+    // Rooms behind the doors of every room, checking that each door has a twin leading back
+    fn door_graph(level: &Level) -> BTreeMap<RoomId, BTreeSet<RoomId>> {
+        let mut graph = BTreeMap::new();
+        for (id, room) in level.rooms.iter() {
+            let neighbours: &mut BTreeSet<RoomId> = graph.entry(*id).or_default();
+            for door in room.load_info.doors.iter() {
+                let other = &level.rooms[&door.room];
+                let way_back = other.load_info.doors.iter().any(|back| {
+                    back.room == *id
+                        && back.cell == door.cell + door.side.offset()
+                        && back.side == door.side.opposite()
+                });
+                assert!(way_back, "door from {} to {} is one way", id, door.room);
+                assert!(
+                    neighbours.insert(door.room),
+                    "two doors from {} to {}",
+                    id,
+                    door.room
+                );
+            }
+        }
+        graph
+    }
+
+    fn distances(graph: &BTreeMap<RoomId, BTreeSet<RoomId>>) -> BTreeMap<RoomId, usize> {
+        let mut distances: BTreeMap<_, _> = std::iter::once((0, 0)).collect();
+        let mut queue: VecDeque<RoomId> = std::iter::once(0).collect();
+        while let Some(id) = queue.pop_front() {
+            let distance = distances[&id];
+            for next in graph[&id].iter() {
+                if !distances.contains_key(next) {
+                    distances.insert(*next, distance + 1);
+                    queue.push_back(*next);
+                }
+            }
+        }
+        distances
+    }
+
+    #[test]
+    fn floors_follow_their_topology() {
+        let templates = templates();
+        for depth in 1..=DEPTHS {
+            let config = FloorConfig::for_depth(depth);
+            let topology = config.topology;
+            for seed in 0..SEEDS {
+                let level = Level::new(&templates, config, seed).unwrap();
+                let context = format!("depth {}, seed {}", depth, seed);
+                let graph = door_graph(&level);
+
+                let distances = distances(&graph);
+                assert_eq!(
+                    distances.len(),
+                    graph.len(),
+                    "unreachable rooms, {}",
+                    context
+                );
+                let deepest = distances.values().max().unwrap();
+                assert!(
+                    *deepest <= topology.max_depth,
+                    "{} deep, {}",
+                    deepest,
+                    context
+                );
+
+                let dead_ends = graph
+                    .iter()
+                    .filter(|(id, neighbours)| **id != 0 && neighbours.len() == 1)
+                    .count();
+                assert!(
+                    dead_ends >= topology.min_dead_ends,
+                    "{} dead ends, {}",
+                    dead_ends,
+                    context
+                );
+
+                // Secret rooms make loops of their own
+                let rooms: Vec<RoomId> = graph
+                    .keys()
+                    .filter(|id| level.rooms[id].room_type != RoomType::Secret)
+                    .cloned()
+                    .collect();
+                let doors = rooms
+                    .iter()
+                    .flat_map(|id| graph[id].iter().filter(|other| rooms.contains(other)))
+                    .count()
+                    / 2;
+                assert_eq!(
+                    doors + 1 - rooms.len(),
+                    topology.loops,
+                    "loops, {}",
+                    context
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn secret_rooms_keep_dead_ends() {
+        let templates = templates();
+        for depth in 1..=DEPTHS {
+            let config = FloorConfig::for_depth(depth);
+            for seed in 0..SEEDS {
+                let mut builder = LevelBuilder::new(seed)
+                    .with_grid_size(config.grid_size)
+                    .with_number_of_rooms(config.room_count)
+                    .with_topology(config.topology);
+                if builder.create_layout(&templates).is_err() {
+                    continue;
+                }
+                let dead_ends = |builder: &LevelBuilder| -> Vec<RoomId> {
+                    builder
+                        .rooms
+                        .keys()
+                        .filter(|id| builder.is_dead_end(**id))
+                        .cloned()
+                        .collect()
+                };
+                let before = dead_ends(&builder);
+                builder.assign_room_types(&templates);
+                assert_eq!(
+                    dead_ends(&builder),
+                    before,
+                    "depth {}, seed {}",
+                    depth,
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rooms_beyond_the_grid_capacity_are_refused() {
+        // One normal and one secret room, but a single cell
+        let result = Level::new(&templates(), config((1, 1), 1, any_topology()), 0);
+        assert!(matches!(
+            result,
+            Err(LevelError::TooManyRooms {
+                requested: 2,
+                capacity: 1
+            })
+        ));
+
+        let result = Level::new(&templates(), config((3, 3), 9, any_topology()), 0);
+        assert!(matches!(
+            result,
+            Err(LevelError::TooManyRooms {
+                requested: 10,
+                capacity: 9
+            })
+        ));
+    }
+
+    #[test]
+    fn floors_without_rooms_are_refused() {
+        let result = Level::new(&templates(), config((3, 3), 0, any_topology()), 0);
+        assert!(matches!(result, Err(LevelError::TooManyRooms { .. })));
+    }
+
+    #[test]
+    fn rooms_that_do_not_fit_the_layout_are_reported() {
+        // Only the four cells next to the start room are within one door of it
+        let topology = Topology {
+            max_depth: 1,
+            ..any_topology()
+        };
+        let result = Level::new(&templates(), config((9, 9), 20, topology), 0);
+        assert!(matches!(
+            result,
+            Err(LevelError::RoomsDoNotFit {
+                requested: 20,
+                placed
+            }) if placed <= 5
+        ));
+    }
+
+    #[test]
+    fn rooms_filling_the_grid_fit() {
+        let level = Level::new(&templates(), config((3, 3), 8, any_topology()), 0).unwrap();
+        assert!(level.room(0).is_some());
+    }
 }
@@ -0,0 +1,317 @@
+use crate::level::room::Room;
+use cgmath::Vector2;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::rc::Rc;
+
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+// Paths cached per room before the cache starts over. Enemies chasing the player search
+// again for every tile it steps on, so old entries are rarely asked for again.
+const PATH_CACHE_SIZE: usize = 256;
+
+pub type Path = Rc<Vec<Vector2<i32>>>;
+
+#[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
+pub struct Movement {
+    pub diagonal: bool,
+    // Without corner cutting a diagonal step needs both adjacent cardinal tiles to be
+    // walkable, otherwise one is enough
+    pub cut_corners: bool,
+}
+
+type PathKey = (Vector2<i32>, Vector2<i32>, Movement);
+
+// Walkability of every tile of a room, indexed the same way as `Room::tiles`
+pub struct NavGrid {
+    dimensions: Vector2<i32>,
+    walkable: Vec<bool>,
+}
+
+impl Default for NavGrid {
+    fn default() -> Self {
+        NavGrid {
+            dimensions: (0, 0).into(),
+            walkable: Vec::new(),
+        }
+    }
+}
+
+impl NavGrid {
+    pub fn from_room(room: &Room) -> Self {
+        let mut walkable = Vec::with_capacity(room.tiles.len());
+        for y in 0..room.dimensions.y {
+            for x in 0..room.dimensions.x {
+                // Enemies keep out of pits and go around spikes
+                walkable.push(room.get_tile(x, y).is_safe());
+            }
+        }
+        NavGrid {
+            dimensions: room.dimensions,
+            walkable,
+        }
+    }
+
+    pub fn is_walkable(&self, pos: Vector2<i32>) -> bool {
+        if pos.x < 0 || pos.x >= self.dimensions.x || pos.y < 0 || pos.y >= self.dimensions.y {
+            return false;
+        }
+        self.walkable[(pos.x + pos.y * self.dimensions.x) as usize]
+    }
+
+    fn neighbours(&self, pos: Vector2<i32>, movement: Movement) -> Vec<(Vector2<i32>, u32)> {
+        let mut neighbours = Vec::with_capacity(8);
+        for &(dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)].iter() {
+            let next = pos + Vector2::new(dx, dy);
+            if self.is_walkable(next) {
+                neighbours.push((next, STRAIGHT_COST));
+            }
+        }
+        if !movement.diagonal {
+            return neighbours;
+        }
+        for &(dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)].iter() {
+            let next = pos + Vector2::new(dx, dy);
+            if !self.is_walkable(next) {
+                continue;
+            }
+            let side_a = self.is_walkable(pos + Vector2::new(dx, 0));
+            let side_b = self.is_walkable(pos + Vector2::new(0, dy));
+            let allowed = if movement.cut_corners {
+                side_a || side_b
+            } else {
+                side_a && side_b
+            };
+            if allowed {
+                neighbours.push((next, DIAGONAL_COST));
+            }
+        }
+        neighbours
+    }
+
+    fn heuristic(from: Vector2<i32>, to: Vector2<i32>, movement: Movement) -> u32 {
+        let dx = (from.x - to.x).unsigned_abs();
+        let dy = (from.y - to.y).unsigned_abs();
+        if movement.diagonal {
+            STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+        } else {
+            (dx + dy) * STRAIGHT_COST
+        }
+    }
+
+    /// A* search between two tile coordinates. The returned path starts with the
+    /// first step after `start` and ends with `goal`.
+    pub fn find_path(
+        &self,
+        start: Vector2<i32>,
+        goal: Vector2<i32>,
+        movement: Movement,
+    ) -> Option<Vec<Vector2<i32>>> {
+        if !self.is_walkable(goal) {
+            return None;
+        }
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<Vector2<i32>, Vector2<i32>> = HashMap::new();
+        let mut cost: HashMap<Vector2<i32>, u32> = HashMap::new();
+        cost.insert(start, 0);
+        open.push(Node {
+            pos: start,
+            cost: 0,
+            estimate: Self::heuristic(start, goal, movement),
+        });
+
+        while let Some(Node { pos, cost: current, .. }) = open.pop() {
+            if pos == goal {
+                let mut path = vec![goal];
+                let mut pos = goal;
+                while let Some(prev) = came_from.get(&pos) {
+                    if *prev == start {
+                        break;
+                    }
+                    path.push(*prev);
+                    pos = *prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            // Stale entry, a cheaper way to this tile was already expanded
+            if current > cost[&pos] {
+                continue;
+            }
+            for (next, step) in self.neighbours(pos, movement) {
+                let next_cost = current + step;
+                if let Some(&known) = cost.get(&next) {
+                    if known <= next_cost {
+                        continue;
+                    }
+                }
+                cost.insert(next, next_cost);
+                came_from.insert(next, pos);
+                open.push(Node {
+                    pos: next,
+                    cost: next_cost,
+                    estimate: next_cost + Self::heuristic(next, goal, movement),
+                });
+            }
+        }
+        None
+    }
+}
+
+#[derive(Eq, PartialEq)]
+struct Node {
+    pos: Vector2<i32>,
+    cost: u32,
+    estimate: u32,
+}
+
+// BinaryHeap is a max heap, so the ordering is reversed. Ties are broken on the
+// position to keep the search deterministic.
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then_with(|| (other.pos.y, other.pos.x).cmp(&(self.pos.y, self.pos.x)))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Per room navigation data. Found paths are cached, since many entities usually
+// chase the same target. Rooms are only borrowed immutably while entities update,
+// hence the RefCell. The cache belongs to the grid, rooms build a new `Navigation`
+// whenever their tiles change.
+#[derive(Default)]
+pub struct Navigation {
+    grid: NavGrid,
+    cache: RefCell<HashMap<PathKey, Option<Path>>>,
+}
+
+impl Navigation {
+    pub fn new(grid: NavGrid) -> Self {
+        Navigation {
+            grid,
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn find_path(
+        &self,
+        start: Vector2<i32>,
+        goal: Vector2<i32>,
+        movement: Movement,
+    ) -> Option<Path> {
+        let key = (start, goal, movement);
+        if let Some(path) = self.cache.borrow().get(&key) {
+            return path.clone();
+        }
+        let path = self.grid.find_path(start, goal, movement).map(Rc::new);
+        let mut cache = self.cache.borrow_mut();
+        if cache.len() >= PATH_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(key, path.clone());
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIAGONAL: Movement = Movement {
+        diagonal: true,
+        cut_corners: false,
+    };
+    const CUT_CORNERS: Movement = Movement {
+        diagonal: true,
+        cut_corners: true,
+    };
+    const STRAIGHT: Movement = Movement {
+        diagonal: false,
+        cut_corners: false,
+    };
+
+    // `#` marks a blocked tile
+    fn grid(rows: &[&str]) -> NavGrid {
+        NavGrid {
+            dimensions: (rows[0].len() as i32, rows.len() as i32).into(),
+            walkable: rows
+                .iter()
+                .flat_map(|row| row.chars().map(|c| c != '#'))
+                .collect(),
+        }
+    }
+
+    fn path(points: &[(i32, i32)]) -> Option<Vec<Vector2<i32>>> {
+        Some(points.iter().map(|&point| point.into()).collect())
+    }
+
+    #[test]
+    fn diagonal_steps_respect_blocked_corners() {
+        let grid = grid(&["..", "#."]);
+        let (start, goal) = ((0, 0).into(), (1, 1).into());
+        assert_eq!(grid.find_path(start, goal, CUT_CORNERS), path(&[(1, 1)]));
+        assert_eq!(
+            grid.find_path(start, goal, DIAGONAL),
+            path(&[(1, 0), (1, 1)])
+        );
+    }
+
+    #[test]
+    fn diagonal_steps_never_squeeze_between_two_walls() {
+        let grid = grid(&[".#", "#."]);
+        let (start, goal) = ((0, 0).into(), (1, 1).into());
+        assert_eq!(grid.find_path(start, goal, CUT_CORNERS), None);
+        assert_eq!(grid.find_path(start, goal, DIAGONAL), None);
+    }
+
+    #[test]
+    fn unreachable_goals_have_no_path() {
+        let grid = grid(&["..#..", "..#..", "..#.."]);
+        let start = (0, 0).into();
+        assert_eq!(grid.find_path(start, (4, 2).into(), CUT_CORNERS), None);
+        assert_eq!(grid.find_path(start, (2, 1).into(), CUT_CORNERS), None);
+        assert_eq!(grid.find_path(start, (7, 0).into(), CUT_CORNERS), None);
+    }
+
+    #[test]
+    fn path_to_the_start_is_the_start() {
+        let grid = grid(&["...", "..."]);
+        let start = (1, 1).into();
+        assert_eq!(grid.find_path(start, start, DIAGONAL), path(&[(1, 1)]));
+    }
+
+    #[test]
+    fn straight_movement_goes_around_walls() {
+        let grid = grid(&["...", "##.", "..."]);
+        assert_eq!(
+            grid.find_path((0, 0).into(), (0, 2).into(), STRAIGHT),
+            path(&[(1, 0), (2, 0), (2, 1), (2, 2), (1, 2), (0, 2)])
+        );
+    }
+
+    #[test]
+    fn path_cache_is_bounded() {
+        let navigation = Navigation::new(grid(&[&".".repeat(32), &".".repeat(32)]));
+        for x in 0..32 {
+            for y in 0..2 {
+                for goal in 0..32 {
+                    navigation.find_path((x, y).into(), (goal, 0).into(), DIAGONAL);
+                }
+            }
+        }
+        assert!(navigation.cache.borrow().len() <= PATH_CACHE_SIZE);
+        let (start, goal) = ((0, 1).into(), (2, 0).into());
+        assert_eq!(
+            navigation.find_path(start, goal, DIAGONAL).as_deref(),
+            path(&[(1, 0), (2, 0)]).as_ref()
+        );
+    }
+}
@@ -1,38 +1,143 @@
-use crate::entity::Direction;
+use crate::entity::{Direction, Entity};
 use crate::graphics::{
     screen::Screen,
     sprite::{SPRITE_SIZE_F32, SPRITE_SIZE_SHIFT_VALUE, SPRITE_SIZE_U32}
 };
-use crate::level::tile;
+use crate::level::{
+    autotile::{self, WallShape},
+    navigation::{Movement, NavGrid, Navigation, Path},
+    template::{Cell, RoomTemplate, TemplateError},
+    tile::{self, Hazard, Prop},
+};
 use cgmath::Vector2;
-use image;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::HashSet;
 use std::convert::From;
 use std::default::Default;
-use std::path::PathBuf;
-
-const MAX_NEIGHBOUR: usize = 4;
 
-pub(crate) type RoomId = u8;
+pub(crate) type RoomId = u16;
 
+// Sides of a grid cell, in the order `RoomTemplate::door_candidates` uses
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
-pub enum Neighbour {
-    North(RoomId),
-    South(RoomId),
-    East(RoomId),
-    West(RoomId),
-    Invalid,
+pub enum Side {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Side {
+    pub const ALL: [Side; 4] = [Side::North, Side::East, Side::South, Side::West];
+
+    // Step to the grid cell on this side
+    pub fn offset(self) -> Vector2<i32> {
+        match self {
+            Side::North => (0, -1).into(),
+            Side::East => (1, 0).into(),
+            Side::South => (0, 1).into(),
+            Side::West => (-1, 0).into(),
+        }
+    }
+
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::North => Side::South,
+            Side::East => Side::West,
+            Side::South => Side::North,
+            Side::West => Side::East,
+        }
+    }
+
+    pub fn direction(self) -> Direction {
+        match self {
+            Side::North => Direction::UP,
+            Side::East => Direction::RIGHT,
+            Side::South => Direction::DOWN,
+            Side::West => Direction::LEFT,
+        }
+    }
+}
+
+// Grid cells covered by a room, relative to the top left corner of its bounding box
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+pub struct RoomShape {
+    cells: Vec<Vector2<i32>>,
+}
+
+impl RoomShape {
+    pub fn single() -> Self {
+        RoomShape {
+            cells: vec![(0, 0).into()],
+        }
+    }
+
+    // The cells are kept sorted row by row, so equal shapes compare equal
+    pub fn new(mut cells: Vec<Vector2<i32>>) -> Self {
+        cells.sort_by_key(|cell| (cell.y, cell.x));
+        cells.dedup();
+        RoomShape { cells }
+    }
+
+    pub fn cells(&self) -> &[Vector2<i32>] {
+        &self.cells
+    }
+
+    pub fn contains(&self, cell: Vector2<i32>) -> bool {
+        self.cells.contains(&cell)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        let mut reached = vec![self.cells[0]];
+        let mut i = 0;
+        while i < reached.len() {
+            for side in Side::ALL.iter() {
+                let next = reached[i] + side.offset();
+                if self.contains(next) && !reached.contains(&next) {
+                    reached.push(next);
+                }
+            }
+            i += 1;
+        }
+        reached.len() == self.cells.len()
+    }
 }
 
-impl Default for Neighbour {
+impl Default for RoomShape {
     fn default() -> Self {
-        Neighbour::Invalid
+        RoomShape::single()
     }
 }
 
+// A door from one cell of a room to the room on the given side of that cell
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct Neighbour {
+    pub cell: Vector2<i32>,
+    pub side: Side,
+    pub room: RoomId,
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
 pub enum RoomType {
     Start,
     Normal,
+    Boss,
+    Treasure,
+    Shop,
+    Secret,
+}
+
+impl RoomType {
+    pub fn parse(name: &str) -> Option<RoomType> {
+        match name {
+            "start" => Some(RoomType::Start),
+            "normal" => Some(RoomType::Normal),
+            "boss" => Some(RoomType::Boss),
+            "treasure" => Some(RoomType::Treasure),
+            "shop" => Some(RoomType::Shop),
+            "secret" => Some(RoomType::Secret),
+            _ => None,
+        }
+    }
 }
 
 impl Default for RoomType {
@@ -41,13 +146,18 @@ impl Default for RoomType {
     }
 }
 
+#[derive(Eq, PartialEq, Debug)]
 pub enum Tiles {
     Empty,
     Grass(usize),
     Ground,
-    Wall(usize, Direction),
-    WallCorner(Direction, Direction),
+    // Variant and the shape the autotiler picked
+    Wall(usize, WallShape),
     SpawnPoint(usize),
+    EnemySpawn(usize),
+    Hazard(Hazard),
+    // Prop and the floor variant left behind once it is broken
+    Prop(Prop, usize),
     Door(Direction),
 }
 
@@ -56,8 +166,8 @@ impl From<u32> for Tiles {
         match num {
             1 => Tiles::Ground,
             2 => Tiles::Grass(0),
-            3 => Tiles::Wall(0, Direction::UP),
-            4 => Tiles::WallCorner(Direction::UP, Direction::LEFT),
+            3 => Tiles::Wall(0, WallShape::Wall(Direction::UP)),
+            4 => Tiles::Wall(0, WallShape::InnerCorner(Direction::UP, Direction::LEFT)),
             5 => Tiles::SpawnPoint(0),
             6 => Tiles::Door(Direction::UP),
             _ => Tiles::Empty,
@@ -66,20 +176,22 @@ impl From<u32> for Tiles {
 }
 
 pub struct RoomBuilder {
-    neighbours: [Neighbour; MAX_NEIGHBOUR],
+    neighbours: Vec<Neighbour>,
     room_type: RoomType,
+    shape: RoomShape,
     grid_pos: Vector2<i32>,
-    path: PathBuf,
+    tileset: usize,
     pub id: RoomId,
 }
 
 impl Default for RoomBuilder {
     fn default() -> RoomBuilder {
         RoomBuilder {
-            neighbours: Default::default(),
+            neighbours: Vec::new(),
             room_type: Default::default(),
+            shape: Default::default(),
             grid_pos: (0, 0).into(),
-            path: Default::default(),
+            tileset: 0,
             id: Default::default(),
         }
     }
@@ -88,21 +200,17 @@ impl Default for RoomBuilder {
 impl RoomBuilder {
     pub fn new() -> RoomBuilder {
         RoomBuilder {
-            neighbours: [Neighbour::Invalid; MAX_NEIGHBOUR],
+            neighbours: Vec::new(),
             room_type: RoomType::Normal,
+            shape: RoomShape::single(),
             grid_pos: (0, 0).into(),
-            path: Default::default(),
+            tileset: 0,
             id: 0,
         }
     }
 
     pub fn add_neighbour(&mut self, neighbour: Neighbour) {
-        for n in self.neighbours.iter_mut() {
-            if *n == Neighbour::Invalid {
-                *n = neighbour;
-                break;
-            }
-        }
+        self.neighbours.push(neighbour);
     }
 
     pub fn with_room_type(mut self, room_type: RoomType) -> RoomBuilder {
@@ -110,13 +218,35 @@ impl RoomBuilder {
         self
     }
 
+    pub fn room_type(&self) -> RoomType {
+        self.room_type
+    }
+
+    pub fn with_shape(mut self, shape: RoomShape) -> RoomBuilder {
+        self.shape = shape;
+        self
+    }
+
+    pub fn shape(&self) -> &RoomShape {
+        &self.shape
+    }
+
+    // Grid cells covered by the room
+    pub fn cells(&self) -> impl Iterator<Item = Vector2<i32>> + '_ {
+        self.shape.cells().iter().map(move |cell| self.grid_pos + cell)
+    }
+
     pub fn with_grid_pos(mut self, grid_pos: Vector2<i32>) -> RoomBuilder {
         self.grid_pos = grid_pos;
         self
     }
 
-    pub fn with_path(mut self, path: &PathBuf) -> RoomBuilder {
-        self.path = path.clone();
+    pub fn grid_pos(&self) -> Vector2<i32> {
+        self.grid_pos
+    }
+
+    pub fn with_tileset(mut self, tileset: usize) -> RoomBuilder {
+        self.tileset = tileset;
         self
     }
 
@@ -125,160 +255,128 @@ impl RoomBuilder {
         self
     }
 
-    pub fn build(self) -> (RoomId, Room) {
-        let image = match image::open(&self.path) {
-            Ok(image) => image.to_rgba(),
-            Err(err) => panic!("Error loading image: {:?} with path {:?}", err, &self.path),
-        };
-        let (width, height) = image.dimensions();
-        let mut tiles = Vec::new();
-        let mut possible_door_positions = Vec::new();
-        for y in 0..height {
-            for x in 0..width {
-                let orientaion = match (x, y) {
-                    (_, 0) => Direction::UP,
-                    (0, _) => Direction::LEFT,
-                    (x, y) => {
-                        if x == width - 1 {
-                            Direction::RIGHT
-                        } else if y == height - 1 {
-                            Direction::DOWN
-                        } else {
-                            Direction::UP
-                        }
-                    }
-                };
-                match image.get_pixel(x, y) {
-                    image::Rgba {
-                        data: [255, 0, 0, 255],
-                    } => {
-                        if x == 0 && y == 0 {
-                            tiles.push(Tiles::WallCorner(Direction::UP, Direction::LEFT));
-                        } else if x == 0 && y == height - 1 {
-                            tiles.push(Tiles::WallCorner(Direction::DOWN, Direction::LEFT));
-                        } else if x == width - 1 && y == 0 {
-                            tiles.push(Tiles::WallCorner(Direction::UP, Direction::RIGHT));
-                        } else if x == width - 1 && y == height - 1 {
-                            tiles.push(Tiles::WallCorner(Direction::DOWN, Direction::RIGHT));
-                        } else {
-                            tiles.push(Tiles::Wall(
-                                rand::thread_rng().gen_range(0_usize, 3),
-                                orientaion,
-                            ));
-                        }
-                    }
-                    image::Rgba {
-                        data: [0, 0, 255, 255],
-                    } => {
-                        possible_door_positions.push(Vector2::new(x, y));
-                        tiles.push(Tiles::Wall(
-                            rand::thread_rng().gen_range(0_usize, 3),
-                            orientaion,
-                        ));
-                    }
-                    image::Rgba {
-                        data: [0, 255, 0, 255],
-                    } => tiles.push(Tiles::Grass(rand::thread_rng().gen_range(0_usize, 6))),
-                    image::Rgba {
-                        data: [255, 255, 0, 255],
-                    } => tiles.push(Tiles::SpawnPoint(rand::thread_rng().gen_range(0_usize, 2))),
-                    _ => tiles.push(Tiles::Empty),
-                }
-            }
-        }
+    pub fn build<R: Rng>(
+        self,
+        template: &RoomTemplate,
+        rng: &mut R,
+    ) -> Result<(RoomId, Room), TemplateError> {
+        let Vector2 { x: width, y: height } = template.dimensions;
+        let seed = rng.gen();
+        let mut tiles = template_tiles(template, &mut StdRng::seed_from_u64(seed));
 
         let mut load_info = LoadInfo::default();
         for neighbour in self.neighbours.iter() {
-            match neighbour {
-                Neighbour::Invalid => continue,
-                Neighbour::North(id) => {
-                    assert!(possible_door_positions.len() > 0);
-                    let mut north_pos = possible_door_positions[0];
-                    let mut idx = 0;
-                    for (i, pos) in possible_door_positions.iter().enumerate() {
-                        if pos.y < north_pos.y {
-                            north_pos = *pos;
-                            idx = i;
-                        }
-                    }
-                    possible_door_positions.remove(idx);
-                    tiles[(north_pos.y * width + north_pos.x) as usize] =
-                        Tiles::Door(Direction::UP);
-                    load_info.doors[0] = Some((north_pos, *id));
-                }
-                Neighbour::East(id) => {
-                    assert!(possible_door_positions.len() > 0);
-                    let mut east_pos = possible_door_positions[0];
-                    let mut idx = 0;
-                    for (i, pos) in possible_door_positions.iter().enumerate() {
-                        if pos.x > east_pos.x {
-                            east_pos = *pos;
-                            idx = i;
-                        }
-                    }
-                    possible_door_positions.remove(idx);
-                    tiles[(east_pos.y * width + east_pos.x) as usize] =
-                        Tiles::Door(Direction::RIGHT);
-                    load_info.doors[1] = Some((east_pos, *id));
-                }
-                Neighbour::South(id) => {
-                    assert!(possible_door_positions.len() > 0);
-                    let mut south_pos = possible_door_positions[0];
-                    let mut idx = 0;
-                    for (i, pos) in possible_door_positions.iter().enumerate() {
-                        if pos.y > south_pos.y {
-                            south_pos = *pos;
-                            idx = i;
-                        }
-                    }
-                    possible_door_positions.remove(idx);
-                    tiles[(south_pos.y * width + south_pos.x) as usize] =
-                        Tiles::Door(Direction::DOWN);
-                    load_info.doors[2] = Some((south_pos, *id));
-                }
-                Neighbour::West(id) => {
-                    assert!(possible_door_positions.len() > 0);
-                    let mut west_pos = possible_door_positions[0];
-                    let mut idx = 0;
-                    for (i, pos) in possible_door_positions.iter().enumerate() {
-                        if pos.x < west_pos.x {
-                            west_pos = *pos;
-                            idx = i;
-                        }
-                    }
-                    possible_door_positions.remove(idx);
-                    tiles[(west_pos.y * width + west_pos.x) as usize] =
-                        Tiles::Door(Direction::LEFT);
-                    load_info.doors[3] = Some((west_pos, *id));
+            let candidates = template.door_candidates(neighbour.cell, neighbour.side);
+            if candidates.is_empty() {
+                return Err(TemplateError::NoDoor {
+                    template: template.name.clone(),
+                    cell: neighbour.cell,
+                    side: neighbour.side,
+                });
+            }
+            let pos = candidates[rng.gen_range(0, candidates.len())];
+            tiles[(pos.y * width + pos.x) as usize] = Tiles::Door(neighbour.side.direction());
+            load_info.doors.push(DoorInfo {
+                tile: pos,
+                side: neighbour.side,
+                cell: self.grid_pos + neighbour.cell,
+                room: neighbour.room,
+            });
+        }
+        let mut room = Room {
+            dimensions: (width as _, height as _).into(),
+            tiles,
+            room_type: self.room_type,
+            grid_pos: self.grid_pos,
+            load_info,
+            seed,
+            template: template.name.clone(),
+            navigation: Default::default(),
+            tileset: self.tileset,
+            walls: template.walls.unwrap_or(self.tileset),
+            visited: false,
+            cleared: false,
+            destroyed: HashSet::new(),
+            entities: Vec::new(),
+        };
+        room.navigation = Navigation::new(NavGrid::from_room(&room));
+        Ok((self.id, room))
+    }
+}
+
+// Tiles of the cells of `template`, doors are cut into them by the room builder. The
+// variants are rolled from the room seed, so retiling keeps the ones of unchanged cells.
+fn template_tiles<R: Rng>(template: &RoomTemplate, rng: &mut R) -> Vec<Tiles> {
+    let Vector2 { x: width, y: height } = template.dimensions;
+    let cell_at = |x: i32, y: i32| {
+        if x < 0 || y < 0 || x as u32 >= width || y as u32 >= height {
+            Cell::Empty
+        } else {
+            template.cell(x as u32, y as u32)
+        }
+    };
+    // Doors are cut into the walls later and shouldn't change how the walls look
+    let is_wall = |x, y| matches!(cell_at(x, y), Cell::Wall | Cell::DoorCandidate);
+    let is_floor = |x, y| !is_wall(x, y) && cell_at(x, y) != Cell::Empty;
+    let mut tiles = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            match template.cell(x, y) {
+                Cell::Wall | Cell::DoorCandidate => {
+                    let (x, y) = (x as i32, y as i32);
+                    let shape = autotile::wall_shape(
+                        autotile::neighbour_mask(x, y, is_floor),
+                        autotile::neighbour_mask(x, y, is_wall),
+                    );
+                    tiles.push(Tiles::Wall(rng.gen_range(0_usize, 3), shape))
                 }
+                Cell::Grass => tiles.push(Tiles::Grass(rng.gen_range(0_usize, 6))),
+                Cell::SpawnPoint => tiles.push(Tiles::SpawnPoint(rng.gen_range(0_usize, 2))),
+                Cell::EnemySpawn => tiles.push(Tiles::EnemySpawn(rng.gen_range(0_usize, 6))),
+                Cell::Hazard(hazard) => tiles.push(Tiles::Hazard(hazard)),
+                Cell::Prop(prop) => tiles.push(Tiles::Prop(prop, rng.gen_range(0_usize, 6))),
+                Cell::Empty => tiles.push(Tiles::Empty),
             }
         }
-        (
-            self.id,
-            Room {
-                neighbours: self.neighbours,
-                dimensions: (width as _, height as _).into(),
-                tiles,
-                room_type: self.room_type,
-                grid_pos: self.grid_pos,
-                load_info,
-            },
-        )
     }
+    tiles
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+#[derive(Debug, Copy, Clone)]
+pub struct DoorInfo {
+    pub tile: Vector2<u32>,
+    pub side: Side,
+    // Grid cell the door is in, the door of the other room is in the cell on `side`
+    pub cell: Vector2<i32>,
+    pub room: RoomId,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct LoadInfo {
-    pub doors: [Option<(Vector2<u32>, RoomId)>; MAX_NEIGHBOUR],
+    pub doors: Vec<DoorInfo>,
 }
 
 pub struct Room {
     pub dimensions: Vector2<i32>,
-    pub neighbours: [Neighbour; MAX_NEIGHBOUR],
     pub tiles: Vec<Tiles>,
     pub room_type: RoomType,
     pub grid_pos: Vector2<i32>,
     pub load_info: LoadInfo,
+    // Seeds everything rolled when the room is entered, independent of the visit order
+    pub seed: u64,
+    // Name of the template the room was built from
+    pub template: String,
+    pub navigation: Navigation,
+    pub tileset: usize,
+    // Wall tileset, the one of the floor unless the template picks another
+    pub walls: usize,
+    // Set once the player entered the room, entities are only spawned on the first visit
+    pub visited: bool,
+    // Set once every hostile entity of the room is gone
+    pub cleared: bool,
+    // Tiles of the props broken so far
+    destroyed: HashSet<usize>,
+    entities: Vec<Box<dyn Entity>>,
 }
 
 fn right_shift_vec(vec: Vector2<i32>, value: u32) -> Vector2<i32> {
@@ -297,6 +395,9 @@ impl Room {
         );
         for y in y0..=y1 {
             for x in x0..=x1 {
+                if let Some(Tiles::Prop(_, i)) = self.tile_index(x, y).map(|i| &self.tiles[i]) {
+                    self.floor_tile(*i).render(x, y, screen, Direction::UP);
+                }
                 let (tile, orientation) = self.get_tile_and_orientation(x, y);
                 tile.render(x, y, screen, orientation);
             }
@@ -313,21 +414,25 @@ impl Room {
             .expect("Out of bounds")
         {
             //Tiles::Ground => (&tile::GROUND_TILES[0], Direction::UP),
-            Tiles::Wall(i, o) => (&tile::WALL_TILES[*i], *o),
-            Tiles::WallCorner(d1, d2) => match (*d1, *d2) {
-                (Direction::UP, Direction::LEFT) => (&tile::CORNER_TILES[0], Direction::UP),
-                (Direction::DOWN, Direction::LEFT) => (&tile::CORNER_TILES[1], Direction::UP),
-                (Direction::UP, Direction::RIGHT) => (&tile::CORNER_TILES[2], Direction::UP),
-                (Direction::DOWN, Direction::RIGHT) => (&tile::CORNER_TILES[3], Direction::UP),
-                _ => (&tile::VOID_TILE, Direction::UP),
-            },
-            Tiles::Grass(i) => (&tile::GRASS_TILES[*i], Direction::UP),
-            Tiles::SpawnPoint(i) => (&tile::GRASS_TILES[*i], Direction::UP),
+            Tiles::Wall(i, shape) => self.wall_tileset().tile(*shape, *i),
+            Tiles::Grass(i) => (self.floor_tile(*i), Direction::UP),
+            Tiles::SpawnPoint(i) => (self.floor_tile(*i), Direction::UP),
+            Tiles::EnemySpawn(i) => (self.floor_tile(*i), Direction::UP),
+            Tiles::Hazard(hazard) => (hazard.tile(), Direction::UP),
+            Tiles::Prop(prop, _) => (prop.tile(), Direction::UP),
             Tiles::Door(o) => (&tile::DOOR_TILE, *o),
             _ => (&tile::VOID_TILE, Direction::UP),
         }
     }
 
+    fn floor_tile(&self, i: usize) -> &'static tile::Tile {
+        tile::FLOOR_TILESETS[self.tileset % tile::FLOOR_TILESETS.len()][i]
+    }
+
+    fn wall_tileset(&self) -> &'static tile::WallTileset {
+        &tile::WALL_TILESETS[self.walls % tile::WALL_TILESETS.len()]
+    }
+
     pub fn get_tile(&self, x: i32, y: i32) -> &'static tile::Tile {
         if x < 0 || x >= self.dimensions.x || y < 0 || y >= self.dimensions.y {
             return &tile::VOID_TILE;
@@ -338,25 +443,105 @@ impl Room {
             .expect("Out of bounds")
         {
             //Tiles::Ground => &tile::GROUND_TILES[0],
-            Tiles::Wall(i, _) => &tile::WALL_TILES[*i],
-            Tiles::WallCorner(d1, d2) => match (*d1, *d2) {
-                (Direction::UP, Direction::LEFT) => &tile::CORNER_TILES[0],
-                (Direction::DOWN, Direction::LEFT) => &tile::CORNER_TILES[1],
-                (Direction::UP, Direction::RIGHT) => &tile::CORNER_TILES[2],
-                (Direction::DOWN, Direction::RIGHT) => &tile::CORNER_TILES[3],
-                _ => &tile::VOID_TILE,
-            },
-            Tiles::Grass(i) => &tile::GRASS_TILES[*i],
-            Tiles::SpawnPoint(i) => &tile::GRASS_TILES[*i],
+            Tiles::Wall(i, shape) => self.wall_tileset().tile(*shape, *i).0,
+            Tiles::Grass(i) => self.floor_tile(*i),
+            Tiles::SpawnPoint(i) => self.floor_tile(*i),
+            Tiles::EnemySpawn(i) => self.floor_tile(*i),
+            Tiles::Hazard(hazard) => hazard.tile(),
+            Tiles::Prop(prop, _) => prop.tile(),
             Tiles::Door(_) => &tile::DOOR_TILE,
             _ => &tile::VOID_TILE,
         }
     }
 
+    // Builds the tiles again from an edited version of the room's template, keeping the
+    // doors where they are and broken props broken. Props painted over are forgotten.
+    pub fn retile(&mut self, template: &RoomTemplate) {
+        self.tiles = template_tiles(template, &mut StdRng::seed_from_u64(self.seed));
+        self.dimensions = template.dimensions.cast().unwrap();
+        self.walls = template.walls.unwrap_or(self.tileset);
+        for door in self.load_info.doors.iter() {
+            let index = (door.tile.y * template.dimensions.x + door.tile.x) as usize;
+            if let Some(tile) = self.tiles.get_mut(index) {
+                *tile = Tiles::Door(door.side.direction());
+            }
+        }
+        let tiles = &mut self.tiles;
+        self.destroyed.retain(|index| match tiles.get(*index) {
+            Some(Tiles::Prop(_, i)) => {
+                tiles[*index] = Tiles::Grass(*i);
+                true
+            }
+            _ => false,
+        });
+        self.navigation = Navigation::new(NavGrid::from_room(self));
+    }
+
+    fn tile_index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || x >= self.dimensions.x || y < 0 || y >= self.dimensions.y {
+            return None;
+        }
+        Some((x + y * self.dimensions.x) as usize)
+    }
+
+    // Breaks the prop at `(x, y)` for good, the room keeps it broken when the player
+    // comes back
+    pub fn destroy_tile(&mut self, x: i32, y: i32) -> Option<Prop> {
+        let index = self.tile_index(x, y)?;
+        match self.tiles[index] {
+            Tiles::Prop(prop, i) => {
+                self.tiles[index] = Tiles::Grass(i);
+                self.destroyed.insert(index);
+                self.navigation = Navigation::new(NavGrid::from_room(self));
+                Some(prop)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn store_entities(&mut self, entities: Vec<Box<dyn Entity>>) {
+        self.entities = entities;
+    }
+
+    pub fn take_entities(&mut self) -> Vec<Box<dyn Entity>> {
+        std::mem::take(&mut self.entities)
+    }
+
+    // Tile coordinates of a point given in pixels
+    pub fn tile_pos(&self, pos: Vector2<f32>) -> Vector2<i32> {
+        right_shift_vec(pos.map(|c| c.floor() as i32), SPRITE_SIZE_SHIFT_VALUE)
+    }
+
+    // Tile under a point given in pixels
+    pub fn tile_at(&self, pos: Vector2<f32>) -> &'static tile::Tile {
+        let Vector2 { x, y } = self.tile_pos(pos);
+        self.get_tile(x, y)
+    }
+
+    pub fn find_path(
+        &self,
+        start: Vector2<i32>,
+        goal: Vector2<i32>,
+        movement: Movement,
+    ) -> Option<Path> {
+        self.navigation.find_path(start, goal, movement)
+    }
+
     pub fn dimensions(&self) -> Vector2<i32> {
         self.dimensions * SPRITE_SIZE_U32 as i32
     }
 
+    // Tiles the template marked for enemies
+    pub fn enemy_spawn_points(&self) -> Vec<Vector2<i32>> {
+        let width = self.dimensions.x;
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| matches!(tile, Tiles::EnemySpawn(_)))
+            .map(|(i, _)| Vector2::new(i as i32 % width, i as i32 / width))
+            .collect()
+    }
+
     pub fn middle_point(&self) -> Vector2<f32> {
         for (i, tile) in self.tiles.iter().enumerate() {
             if let Tiles::SpawnPoint(_) = tile {
@@ -370,3 +555,130 @@ impl Room {
         return (0., 0.).into();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::template::TemplateRegistry;
+
+    #[test]
+    fn retiling_keeps_broken_props_broken() {
+        let templates = TemplateRegistry::load(&TemplateRegistry::default_dir()).unwrap();
+        let mut template = templates.template("props.png").unwrap().clone();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (_, mut room) = RoomBuilder::new().build(&template, &mut rng).unwrap();
+        let index = room
+            .tiles
+            .iter()
+            .position(|tile| matches!(tile, Tiles::Prop(..)))
+            .unwrap();
+        let width = room.dimensions.x;
+        let (x, y) = (index as i32 % width, index as i32 / width);
+        assert!(room.destroy_tile(x, y).is_some());
+
+        room.retile(&template);
+        assert!(matches!(room.tiles[index], Tiles::Grass(_)));
+
+        // A prop painted again in place of the broken one is a new prop
+        let cell = template.cell(x as u32, y as u32);
+        template.set_cell(x as u32, y as u32, Cell::Grass);
+        room.retile(&template);
+        template.set_cell(x as u32, y as u32, cell);
+        room.retile(&template);
+        assert!(matches!(room.tiles[index], Tiles::Prop(..)));
+    }
+
+    #[test]
+    fn paths_follow_the_tiles_after_they_change() {
+        let templates = TemplateRegistry::load(&TemplateRegistry::default_dir()).unwrap();
+        let mut template = templates.template("props.png").unwrap().clone();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (_, mut room) = RoomBuilder::new().build(&template, &mut rng).unwrap();
+        let movement = Movement {
+            diagonal: true,
+            cut_corners: false,
+        };
+        // A rock, and the floor tile left of it
+        let (rock, start) = (Vector2::new(3, 2), Vector2::new(2, 2));
+        assert!(room.find_path(start, rock, movement).is_none());
+        room.destroy_tile(rock.x, rock.y);
+        assert_eq!(
+            room.find_path(start, rock, movement).unwrap().as_slice(),
+            &[rock]
+        );
+
+        template.set_cell(rock.x as u32, rock.y as u32, Cell::Wall);
+        room.retile(&template);
+        assert!(room.find_path(start, rock, movement).is_none());
+    }
+
+    #[test]
+    fn templates_can_pick_their_wall_tileset() {
+        let templates = TemplateRegistry::load(&TemplateRegistry::default_dir()).unwrap();
+        let sandstone = tile::WallTileset::parse("sandstone").unwrap();
+        let mut template = templates.template("empty.png").unwrap().clone();
+        assert_eq!(template.walls, None);
+        let mut rng = StdRng::seed_from_u64(0);
+        let (_, mut room) = RoomBuilder::new()
+            .with_tileset(2)
+            .build(&template, &mut rng)
+            .unwrap();
+        assert_eq!(room.walls, 2);
+
+        template.walls = Some(sandstone);
+        room.retile(&template);
+        assert_eq!(room.walls, sandstone);
+        assert!(std::ptr::eq(
+            room.wall_tileset(),
+            &tile::WALL_TILESETS[sandstone]
+        ));
+        let (_, room) = RoomBuilder::new()
+            .with_tileset(2)
+            .build(&template, &mut rng)
+            .unwrap();
+        assert_eq!(room.walls, sandstone);
+        assert_eq!(room.tileset, 2);
+    }
+
+    #[test]
+    fn destroyed_props_become_floor_once() {
+        let templates = TemplateRegistry::load(&TemplateRegistry::default_dir()).unwrap();
+        let template = templates.template("props.png").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (_, mut room) = RoomBuilder::new().build(template, &mut rng).unwrap();
+        let (x, y) = (3, 2);
+        let index = room.tile_index(x, y).unwrap();
+        assert!(room.get_tile(x, y).solid);
+        assert!(room.get_tile(x, y).breakable);
+
+        assert_eq!(room.destroy_tile(x, y), Some(Prop::Rock));
+        assert!(!room.get_tile(x, y).solid);
+        assert!(!room.get_tile(x, y).breakable);
+        assert!(room.destroyed.contains(&index));
+
+        // Floor and walls don't break
+        assert_eq!(room.destroy_tile(x, y), None);
+        assert_eq!(room.destroy_tile(0, 0), None);
+        assert_eq!(room.destroyed.len(), 1);
+    }
+
+    #[test]
+    fn retiling_keeps_the_tile_variants_and_follows_the_size() {
+        let templates = TemplateRegistry::load(&TemplateRegistry::default_dir()).unwrap();
+        let template = templates.template("props.png").unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (_, mut room) = RoomBuilder::new().build(template, &mut rng).unwrap();
+        let tiles = template_tiles(template, &mut StdRng::seed_from_u64(room.seed));
+        assert_eq!(room.tiles, tiles);
+        room.retile(template);
+        assert_eq!(room.tiles, tiles);
+
+        let wide = templates.template("wide.png").unwrap();
+        room.retile(wide);
+        assert_eq!(room.dimensions, wide.dimensions.cast().unwrap());
+        assert_eq!(
+            room.tiles.len(),
+            (wide.dimensions.x * wide.dimensions.y) as usize
+        );
+    }
+}
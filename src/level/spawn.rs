@@ -0,0 +1,205 @@
+use cgmath::Vector2;
+use rand::{seq::SliceRandom, Rng};
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum EnemyKind {
+    Blob,
+}
+
+impl EnemyKind {
+    pub fn parse(name: &str) -> Option<EnemyKind> {
+        match name {
+            "blob" => Some(EnemyKind::Blob),
+            _ => None,
+        }
+    }
+}
+
+// A weighted enemy of a spawn table, used on the floors `first_floor..=last_floor`
+#[derive(Debug, Clone)]
+pub struct SpawnEntry {
+    pub enemy: EnemyKind,
+    pub first_floor: u32,
+    pub last_floor: Option<u32>,
+    pub weight: u32,
+    // Points taken from the room budget
+    pub cost: u32,
+    pub speed: f32,
+}
+
+impl SpawnEntry {
+    fn is_on_floor(&self, depth: u32) -> bool {
+        depth >= self.first_floor && self.last_floor.map(|last| depth <= last) != Some(false)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Spawn {
+    pub enemy: EnemyKind,
+    // Tile the enemy is placed on
+    pub tile: Vector2<i32>,
+    pub speed: f32,
+}
+
+#[derive(Default)]
+pub struct SpawnTable {
+    entries: Vec<SpawnEntry>,
+}
+
+impl SpawnTable {
+    pub fn add(&mut self, entry: SpawnEntry) {
+        self.entries.push(entry);
+    }
+
+    /// Spends up to `budget` points on enemies of floor `depth`, each placed on a
+    /// different one of `spawn_points`.
+    pub fn roll<R: Rng>(
+        &self,
+        depth: u32,
+        budget: u32,
+        spawn_points: &[Vector2<i32>],
+        rng: &mut R,
+    ) -> Vec<Spawn> {
+        let mut points = spawn_points.to_vec();
+        points.shuffle(rng);
+        let mut budget = budget;
+        let mut spawns = Vec::new();
+        for tile in points {
+            let affordable: Vec<&SpawnEntry> = self
+                .entries
+                .iter()
+                .filter(|entry| entry.is_on_floor(depth) && entry.cost <= budget)
+                .collect();
+            let total: u32 = affordable.iter().map(|entry| entry.weight).sum();
+            if total == 0 {
+                break;
+            }
+            let mut roll = rng.gen_range(0, total);
+            for entry in affordable {
+                if roll < entry.weight {
+                    budget -= entry.cost;
+                    spawns.push(Spawn {
+                        enemy: entry.enemy,
+                        tile,
+                        speed: entry.speed,
+                    });
+                    break;
+                }
+                roll -= entry.weight;
+            }
+        }
+        spawns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const SEEDS: u64 = 50;
+
+    // Entries are told apart by their speed in the spawns
+    fn entry(speed: f32, floors: (u32, Option<u32>), weight: u32, cost: u32) -> SpawnEntry {
+        SpawnEntry {
+            enemy: EnemyKind::Blob,
+            first_floor: floors.0,
+            last_floor: floors.1,
+            weight,
+            cost,
+            speed,
+        }
+    }
+
+    fn table(entries: Vec<SpawnEntry>) -> SpawnTable {
+        let mut table = SpawnTable::default();
+        for entry in entries {
+            table.add(entry);
+        }
+        table
+    }
+
+    fn spawn_points(count: i32) -> Vec<Vector2<i32>> {
+        (0..count).map(|x| Vector2::new(x, 0)).collect()
+    }
+
+    fn rolls(table: &SpawnTable, depth: u32, budget: u32) -> Vec<Vec<Spawn>> {
+        (0..SEEDS)
+            .map(|seed| {
+                let mut rng = StdRng::seed_from_u64(seed);
+                table.roll(depth, budget, &spawn_points(20), &mut rng)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn budget_is_never_exceeded() {
+        let entries = vec![entry(1., (1, None), 3, 1), entry(2., (1, None), 1, 3)];
+        let cost = |speed: f32| entries.iter().find(|e| e.speed == speed).unwrap().cost;
+        let table = table(entries.clone());
+        for budget in 0..8 {
+            for spawns in rolls(&table, 1, budget) {
+                let spent: u32 = spawns.iter().map(|spawn| cost(spawn.speed)).sum();
+                // There are more spawn points than points to spend, so the cheap entry
+                // uses up whatever the expensive one leaves
+                assert_eq!(spent, budget);
+                let mut tiles: Vec<_> = spawns
+                    .iter()
+                    .map(|spawn| (spawn.tile.x, spawn.tile.y))
+                    .collect();
+                tiles.sort();
+                tiles.dedup();
+                assert_eq!(tiles.len(), spawns.len());
+            }
+        }
+    }
+
+    #[test]
+    fn spawns_run_out_with_the_spawn_points() {
+        let table = table(vec![entry(1., (1, None), 1, 1)]);
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(table.roll(1, 10, &spawn_points(3), &mut rng).len(), 3);
+        assert!(table.roll(1, 10, &[], &mut rng).is_empty());
+    }
+
+    #[test]
+    fn entries_only_spawn_on_their_floors() {
+        let table = table(vec![
+            entry(1., (1, Some(2)), 1, 1),
+            entry(2., (2, None), 1, 1),
+            entry(3., (3, Some(3)), 1, 1),
+        ]);
+        let speeds = |depth| -> Vec<f32> {
+            let mut speeds: Vec<f32> = rolls(&table, depth, 4)
+                .iter()
+                .flatten()
+                .map(|spawn| spawn.speed)
+                .collect();
+            speeds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            speeds.dedup();
+            speeds
+        };
+        assert_eq!(speeds(1), vec![1.]);
+        assert_eq!(speeds(2), vec![1., 2.]);
+        assert_eq!(speeds(3), vec![2., 3.]);
+        assert_eq!(speeds(4), vec![2.]);
+    }
+
+    #[test]
+    fn empty_tables_spawn_nothing() {
+        assert!(rolls(&SpawnTable::default(), 1, 10)
+            .iter()
+            .all(Vec::is_empty));
+    }
+
+    #[test]
+    fn entries_without_weight_are_never_chosen() {
+        let unweighted = table(vec![entry(1., (1, None), 0, 1)]);
+        assert!(rolls(&unweighted, 1, 10).iter().all(Vec::is_empty));
+
+        let mixed = table(vec![entry(1., (1, None), 0, 1), entry(2., (1, None), 1, 1)]);
+        let spawns: Vec<Spawn> = rolls(&mixed, 1, 10).into_iter().flatten().collect();
+        assert!(!spawns.is_empty());
+        assert!(spawns.iter().all(|spawn| spawn.speed == 2.));
+    }
+}
@@ -0,0 +1,582 @@
+use crate::level::{
+    room::{RoomShape, RoomType, Side},
+    spawn::{EnemyKind, SpawnEntry, SpawnTable},
+    tile::{Hazard, Prop, WallTileset},
+    tiled,
+};
+use cgmath::Vector2;
+use rand::Rng;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// File layout: a header line followed by `palette` lines mapping an RGBA colour to
+// a cell, `template` lines listing the weighted images or Tiled maps (`.json`) of every
+// room type, optionally with the wall tileset they are drawn with, and `spawn` lines
+// making up the spawn table. Empty lines and lines starting with `#` are ignored.
+const HEADER: &str = "atoma-templates 1";
+pub const MANIFEST_NAME: &str = "templates.txt";
+// Tiles of a room covering a single grid cell. Larger templates cover several cells,
+// cells that are completely empty are not part of the room.
+pub const CELL_TILES: Vector2<u32> = Vector2::new(15, 9);
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Io(PathBuf, io::Error),
+    Image(PathBuf, image::ImageError),
+    Tiled(PathBuf, String),
+    Parse { line: usize, reason: String },
+    UnknownColour { template: String, pos: Vector2<u32>, colour: [u8; 4] },
+    BadShape(String),
+    NoDoor { template: String, cell: Vector2<i32>, side: Side },
+    NoTemplate(RoomType),
+    NoColour { template: String, cell: Cell },
+    // Tiled maps are edited in Tiled, only image templates are written back
+    NotAnImage(PathBuf),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Io(path, err) => write!(f, "{:?}: {}", path, err),
+            TemplateError::Image(path, err) => write!(f, "{:?}: {}", path, err),
+            TemplateError::Tiled(path, reason) => write!(f, "{:?}: {}", path, reason),
+            TemplateError::Parse { line, reason } => {
+                write!(f, "{} line {}: {}", MANIFEST_NAME, line, reason)
+            }
+            TemplateError::UnknownColour {
+                template,
+                pos,
+                colour,
+            } => write!(
+                f,
+                "{}: colour {:?} at ({}, {}) is not in the palette",
+                template, colour, pos.x, pos.y
+            ),
+            TemplateError::BadShape(template) => write!(
+                f,
+                "{}: size has to be a multiple of {}x{} tiles with connected cells",
+                template, CELL_TILES.x, CELL_TILES.y
+            ),
+            TemplateError::NoDoor {
+                template,
+                cell,
+                side,
+            } => write!(
+                f,
+                "{}: no door candidate on the {:?} side of cell ({}, {})",
+                template, side, cell.x, cell.y
+            ),
+            TemplateError::NoTemplate(room_type) => {
+                write!(f, "no template for {:?} rooms", room_type)
+            }
+            TemplateError::NoColour { template, cell } => {
+                write!(f, "{}: no palette colour for {:?} cells", template, cell)
+            }
+            TemplateError::NotAnImage(path) => {
+                write!(f, "{:?}: only image templates can be saved", path)
+            }
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum Cell {
+    Empty,
+    Wall,
+    DoorCandidate,
+    Grass,
+    SpawnPoint,
+    EnemySpawn,
+    Hazard(Hazard),
+    Prop(Prop),
+}
+
+impl Cell {
+    pub fn parse(name: &str) -> Option<Cell> {
+        match name {
+            "empty" => Some(Cell::Empty),
+            "wall" => Some(Cell::Wall),
+            "door" => Some(Cell::DoorCandidate),
+            "grass" => Some(Cell::Grass),
+            "spawn" => Some(Cell::SpawnPoint),
+            "enemy" => Some(Cell::EnemySpawn),
+            _ => Hazard::parse(name)
+                .map(Cell::Hazard)
+                .or_else(|| Prop::parse(name).map(Cell::Prop)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RoomTemplate {
+    pub name: String,
+    pub dimensions: Vector2<u32>,
+    pub cells: Vec<Cell>,
+    pub shape: RoomShape,
+    // Wall tileset picked by the manifest, rooms use the one of their floor otherwise
+    pub walls: Option<usize>,
+    weight: u32,
+    path: PathBuf,
+}
+
+impl RoomTemplate {
+    pub fn cell(&self, x: u32, y: u32) -> Cell {
+        self.cells[(y * self.dimensions.x + x) as usize]
+    }
+
+    pub fn set_cell(&mut self, x: u32, y: u32, cell: Cell) {
+        self.cells[(y * self.dimensions.x + x) as usize] = cell;
+    }
+
+    // Door candidates on the edge of a grid cell of the template
+    pub fn door_candidates(&self, cell: Vector2<i32>, side: Side) -> Vec<Vector2<u32>> {
+        let Vector2 { x: width, y: height } = CELL_TILES;
+        let x0 = cell.x as u32 * width;
+        let y0 = cell.y as u32 * height;
+        let edge: Vec<Vector2<u32>> = match side {
+            Side::North => (x0..x0 + width).map(|x| Vector2::new(x, y0)).collect(),
+            Side::South => (x0..x0 + width).map(|x| Vector2::new(x, y0 + height - 1)).collect(),
+            Side::West => (y0..y0 + height).map(|y| Vector2::new(x0, y)).collect(),
+            Side::East => (y0..y0 + height).map(|y| Vector2::new(x0 + width - 1, y)).collect(),
+        };
+        edge.into_iter()
+            .filter(|pos| self.cell(pos.x, pos.y) == Cell::DoorCandidate)
+            .collect()
+    }
+
+    // Grid cells with at least one non empty tile
+    fn find_shape(dimensions: Vector2<u32>, cells: &[Cell]) -> Option<RoomShape> {
+        if (dimensions.x % CELL_TILES.x, dimensions.y % CELL_TILES.y) != (0, 0) {
+            return None;
+        }
+        let mut shape_cells = Vec::new();
+        for cy in 0..dimensions.y / CELL_TILES.y {
+            for cx in 0..dimensions.x / CELL_TILES.x {
+                let used = (0..CELL_TILES.y).any(|y| {
+                    (0..CELL_TILES.x).any(|x| {
+                        let pos = Vector2::new(cx * CELL_TILES.x + x, cy * CELL_TILES.y + y);
+                        cells[(pos.y * dimensions.x + pos.x) as usize] != Cell::Empty
+                    })
+                });
+                if used {
+                    shape_cells.push(Vector2::new(cx as i32, cy as i32));
+                }
+            }
+        }
+        if shape_cells.is_empty() {
+            return None;
+        }
+        Some(RoomShape::new(shape_cells)).filter(RoomShape::is_connected)
+    }
+}
+
+// Floors `first-last`, `first-` or a single floor
+fn parse_floors(floors: &str) -> Option<(u32, Option<u32>)> {
+    let (first, last) = match floors.find('-') {
+        Some(i) => (&floors[..i], Some(&floors[i + 1..])),
+        None => (floors, Some(floors)),
+    };
+    let first: u32 = first.parse().ok()?;
+    let last = match last {
+        Some("") => None,
+        Some(last) => Some(last.parse().ok()?),
+        None => None,
+    };
+    if first == 0 || last.map(|last| last < first) == Some(true) {
+        return None;
+    }
+    Some((first, last))
+}
+
+pub struct TemplateRegistry {
+    templates: HashMap<RoomType, Vec<RoomTemplate>>,
+    palette: HashMap<[u8; 4], Cell>,
+    spawn_table: SpawnTable,
+}
+
+impl TemplateRegistry {
+    pub fn default_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("res/rooms")
+    }
+
+    /// Loads the manifest of `dir` and decodes every template image it lists.
+    pub fn load(dir: &Path) -> Result<TemplateRegistry, TemplateError> {
+        let manifest_path = dir.join(MANIFEST_NAME);
+        let manifest = fs::read_to_string(&manifest_path)
+            .map_err(|err| TemplateError::Io(manifest_path.clone(), err))?;
+        let parse_error = |line: usize, reason: &str| TemplateError::Parse {
+            line,
+            reason: reason.to_owned(),
+        };
+
+        let mut lines = manifest.lines().enumerate();
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(parse_error(1, "missing header"));
+        }
+        let mut palette = HashMap::new();
+        let mut entries = Vec::new();
+        let mut spawn_table = SpawnTable::default();
+        for (i, line) in lines {
+            let line_number = i + 1;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [] => {}
+                [comment, ..] if comment.starts_with('#') => {}
+                ["palette", r, g, b, a, cell] => {
+                    let mut colour = [0; 4];
+                    for (channel, field) in colour.iter_mut().zip([r, g, b, a].iter()) {
+                        *channel = field
+                            .parse()
+                            .map_err(|_| parse_error(line_number, "invalid colour"))?;
+                    }
+                    let cell =
+                        Cell::parse(cell).ok_or_else(|| parse_error(line_number, "unknown cell"))?;
+                    palette.insert(colour, cell);
+                }
+                ["template", room_type, weight, image, walls @ ..] if walls.len() <= 1 => {
+                    let room_type = RoomType::parse(room_type)
+                        .ok_or_else(|| parse_error(line_number, "unknown room type"))?;
+                    let weight: u32 = weight
+                        .parse()
+                        .map_err(|_| parse_error(line_number, "invalid weight"))?;
+                    if weight == 0 {
+                        return Err(parse_error(line_number, "weight must be positive"));
+                    }
+                    let walls = match walls.first() {
+                        Some(walls) => Some(
+                            WallTileset::parse(walls)
+                                .ok_or_else(|| parse_error(line_number, "unknown wall tileset"))?,
+                        ),
+                        None => None,
+                    };
+                    entries.push((room_type, weight, dir.join(image), walls));
+                }
+                ["spawn", enemy, floors, weight, cost, speed] => {
+                    let enemy = EnemyKind::parse(enemy)
+                        .ok_or_else(|| parse_error(line_number, "unknown enemy"))?;
+                    let (first_floor, last_floor) = parse_floors(floors)
+                        .ok_or_else(|| parse_error(line_number, "invalid floors"))?;
+                    let weight: u32 = weight
+                        .parse()
+                        .map_err(|_| parse_error(line_number, "invalid weight"))?;
+                    let cost: u32 = cost
+                        .parse()
+                        .map_err(|_| parse_error(line_number, "invalid cost"))?;
+                    if weight == 0 || cost == 0 {
+                        return Err(parse_error(line_number, "weight and cost must be positive"));
+                    }
+                    let speed: f32 = speed
+                        .parse()
+                        .map_err(|_| parse_error(line_number, "invalid speed"))?;
+                    spawn_table.add(SpawnEntry {
+                        enemy,
+                        first_floor,
+                        last_floor,
+                        weight,
+                        cost,
+                        speed,
+                    });
+                }
+                _ => return Err(parse_error(line_number, "unknown entry")),
+            }
+        }
+
+        let mut templates: HashMap<RoomType, Vec<RoomTemplate>> = HashMap::new();
+        for (room_type, weight, path, walls) in entries {
+            let template = Self::decode(&path, &palette, weight, walls)?;
+            templates.entry(room_type).or_default().push(template);
+        }
+        Ok(TemplateRegistry {
+            templates,
+            palette,
+            spawn_table,
+        })
+    }
+
+    fn decode(
+        path: &Path,
+        palette: &HashMap<[u8; 4], Cell>,
+        weight: u32,
+        walls: Option<usize>,
+    ) -> Result<RoomTemplate, TemplateError> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (dimensions, cells) = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") | Some("tmx") => {
+                tiled::load(path).map_err(|reason| TemplateError::Tiled(path.to_owned(), reason))?
+            }
+            _ => Self::decode_image(path, palette, &name)?,
+        };
+        let mut template = RoomTemplate {
+            name,
+            dimensions,
+            cells,
+            shape: RoomShape::single(),
+            walls,
+            weight,
+            path: path.to_owned(),
+        };
+        Self::check(&mut template)?;
+        Ok(template)
+    }
+
+    // Finds the shape of the template and makes sure every outer side of it has a door
+    fn check(template: &mut RoomTemplate) -> Result<(), TemplateError> {
+        template.shape = match RoomTemplate::find_shape(template.dimensions, &template.cells) {
+            Some(shape) => shape,
+            None => return Err(TemplateError::BadShape(template.name.clone())),
+        };
+
+        // Any room can get a neighbour on every outer side of its cells
+        for cell in template.shape.cells() {
+            for side in Side::ALL.iter() {
+                let outer = !template.shape.contains(cell + side.offset());
+                if outer && template.door_candidates(*cell, *side).is_empty() {
+                    return Err(TemplateError::NoDoor {
+                        template: template.name.clone(),
+                        cell: *cell,
+                        side: *side,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_image(
+        path: &Path,
+        palette: &HashMap<[u8; 4], Cell>,
+        name: &str,
+    ) -> Result<(Vector2<u32>, Vec<Cell>), TemplateError> {
+        let image = image::open(path)
+            .map_err(|err| TemplateError::Image(path.to_owned(), err))?
+            .to_rgba();
+        let (width, height) = image.dimensions();
+        let mut cells = Vec::with_capacity((width * height) as usize);
+        for (x, y, pixel) in image.enumerate_pixels() {
+            match palette.get(&pixel.data) {
+                Some(cell) => cells.push(*cell),
+                None => {
+                    return Err(TemplateError::UnknownColour {
+                        template: name.to_owned(),
+                        pos: (x, y).into(),
+                        colour: pixel.data,
+                    })
+                }
+            }
+        }
+        Ok(((width, height).into(), cells))
+    }
+
+    pub fn spawn_table(&self) -> &SpawnTable {
+        &self.spawn_table
+    }
+
+    pub fn template(&self, name: &str) -> Option<&RoomTemplate> {
+        self.templates
+            .values()
+            .flatten()
+            .find(|template| template.name == name)
+    }
+
+    // Colour painting `cell` in template images, the lowest one if several map to it
+    pub fn colour(&self, cell: Cell) -> Option<[u8; 4]> {
+        self.palette
+            .iter()
+            .filter(|(_, palette_cell)| **palette_cell == cell)
+            .map(|(colour, _)| *colour)
+            .min()
+    }
+
+    /// Writes `template` over its image in the palette colours and uses its cells from now
+    /// on in every template of the same name. Returns the path of the image.
+    pub fn save(&mut self, mut template: RoomTemplate) -> Result<PathBuf, TemplateError> {
+        if template.path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+            return Err(TemplateError::NotAnImage(template.path));
+        }
+        Self::check(&mut template)?;
+        let Vector2 { x: width, y: height } = template.dimensions;
+        let mut image = image::RgbaImage::new(width, height);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let cell = template.cell(x, y);
+            let colour = self.colour(cell).ok_or_else(|| TemplateError::NoColour {
+                template: template.name.clone(),
+                cell,
+            })?;
+            *pixel = image::Rgba(colour);
+        }
+        let path = template.path.clone();
+        image
+            .save(&path)
+            .map_err(|err| TemplateError::Io(path.clone(), err))?;
+        // The same image may be listed for several room types, each with its own weight
+        let copies = self
+            .templates
+            .values_mut()
+            .flatten()
+            .filter(|old| old.name == template.name);
+        for old in copies {
+            old.dimensions = template.dimensions;
+            old.cells = template.cells.clone();
+            old.shape = template.shape.clone();
+        }
+        Ok(path)
+    }
+
+    /// Picks a random template of `room_type` and `shape`, proportionally to the
+    /// template weights.
+    pub fn choose<R: Rng>(
+        &self,
+        room_type: RoomType,
+        shape: &RoomShape,
+        rng: &mut R,
+    ) -> Result<&RoomTemplate, TemplateError> {
+        let templates: Vec<&RoomTemplate> = self
+            .templates
+            .get(&room_type)
+            .into_iter()
+            .flatten()
+            .filter(|template| template.shape == *shape)
+            .collect();
+        if templates.is_empty() {
+            return Err(TemplateError::NoTemplate(room_type));
+        }
+        let total: u32 = templates.iter().map(|template| template.weight).sum();
+        let mut roll = rng.gen_range(0, total);
+        for template in templates {
+            if roll < template.weight {
+                return Ok(template);
+            }
+            roll -= template.weight;
+        }
+        unreachable!()
+    }
+
+    /// Picks the shape of a random template of `room_type`, proportionally to the
+    /// template weights.
+    pub fn choose_shape<R: Rng>(&self, room_type: RoomType, rng: &mut R) -> Option<&RoomShape> {
+        let templates = self.templates.get(&room_type)?;
+        let total: u32 = templates.iter().map(|template| template.weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = rng.gen_range(0, total);
+        for template in templates {
+            if roll < template.weight {
+                return Some(&template.shape);
+            }
+            roll -= template.weight;
+        }
+        None
+    }
+
+    pub fn has_shape(&self, room_type: RoomType, shape: &RoomShape) -> bool {
+        self.templates
+            .get(&room_type)
+            .map(|templates| templates.iter().any(|template| template.shape == *shape))
+            == Some(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A copy of the bundled templates that tests can write to
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("atoma-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for entry in fs::read_dir(TemplateRegistry::default_dir()).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn saved_templates_replace_their_image() {
+        let dir = scratch_dir("save");
+        let mut templates = TemplateRegistry::load(&dir).unwrap();
+        let mut template = templates.template("empty.png").unwrap().clone();
+        template.set_cell(3, 3, Cell::Prop(Prop::Rock));
+        let path = templates.save(template).unwrap();
+        assert_eq!(path, dir.join("empty.png"));
+        let cell =
+            |templates: &TemplateRegistry| templates.template("empty.png").unwrap().cell(3, 3);
+        assert_eq!(cell(&templates), Cell::Prop(Prop::Rock));
+        assert_eq!(
+            cell(&TemplateRegistry::load(&dir).unwrap()),
+            Cell::Prop(Prop::Rock)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tiled_templates_are_not_saved() {
+        let dir = scratch_dir("tiled");
+        let mut templates = TemplateRegistry::load(&dir).unwrap();
+        let mut template = templates.template("arena.json").unwrap().clone();
+        let before = template.cell(3, 3);
+        template.set_cell(3, 3, Cell::Prop(Prop::Rock));
+        assert!(matches!(
+            templates.save(template),
+            Err(TemplateError::NotAnImage(_))
+        ));
+        assert!(!dir.join("arena.png").exists());
+        assert_eq!(templates.template("arena.json").unwrap().cell(3, 3), before);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn templates_name_their_wall_tileset() {
+        let dir = scratch_dir("walls");
+        let manifest = fs::read_to_string(dir.join(MANIFEST_NAME)).unwrap();
+        let templates = TemplateRegistry::load(&dir).unwrap();
+        assert_eq!(
+            templates.template("hazards.png").unwrap().walls,
+            WallTileset::parse("sandstone")
+        );
+        assert_eq!(templates.template("pillars.png").unwrap().walls, None);
+
+        let broken = manifest.replace("hazards.png sandstone", "hazards.png marble");
+        fs::write(dir.join(MANIFEST_NAME), broken).unwrap();
+        assert!(matches!(
+            TemplateRegistry::load(&dir),
+            Err(TemplateError::Parse { reason, .. }) if reason == "unknown wall tileset"
+        ));
+        let broken = manifest.replace("hazards.png sandstone", "hazards.png sandstone stone");
+        fs::write(dir.join(MANIFEST_NAME), broken).unwrap();
+        assert!(matches!(
+            TemplateRegistry::load(&dir),
+            Err(TemplateError::Parse { reason, .. }) if reason == "unknown entry"
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saving_updates_every_room_type_of_a_template() {
+        let dir = scratch_dir("types");
+        let mut templates = TemplateRegistry::load(&dir).unwrap();
+        let mut template = templates.template("empty.png").unwrap().clone();
+        template.set_cell(3, 3, Cell::Prop(Prop::Pot));
+        templates.save(template).unwrap();
+
+        let copies = |room_type| {
+            templates.templates[&room_type]
+                .iter()
+                .filter(|template| template.name == "empty.png")
+                .map(|template| (template.weight, template.cell(3, 3)))
+                .collect::<Vec<_>>()
+        };
+        let pot = Cell::Prop(Prop::Pot);
+        assert_eq!(copies(RoomType::Start), vec![(1, pot)]);
+        assert_eq!(copies(RoomType::Normal), vec![(3, pot)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
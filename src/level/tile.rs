@@ -2,10 +2,12 @@ use crate::entity::Direction;
 use crate::graphics::{
     screen::Screen,
     sprite::{
-        Sprite, CORNERS, CURRENT_ROOM, DOOR, GRASSES, NO_ROOM, ROOM, SPRITE_SIZE_SHIFT_VALUE, VOID,
-        WALLS,
+        Sprite, BRUSH, CORNERS, CRATE, DOOR, EDGES, GRASSES, MUD, OUTER_CORNERS, PIT, POT, ROCK,
+        RUBBLES, SANDSTONE_CORNERS, SANDSTONE_EDGES, SANDSTONE_OUTER_CORNERS, SANDSTONE_WALLS,
+        SANDSTONE_WALL_TOP, SLABS, SPIKES, SPRITE_SIZE_SHIFT_VALUE, VOID, WALLS, WALL_TOP, WATER,
     },
 };
+use crate::level::autotile::WallShape;
 
 lazy_static! {
     static ref GRASS_TILE0: Tile = Tile::new(GRASSES[0], false);
@@ -23,9 +25,17 @@ lazy_static! {
     static ref WALL_TILE2: Tile = Tile::new(WALLS[2], true);
     pub static ref VOID_TILE: Tile = Tile::new(&VOID, true);
     pub static ref DOOR_TILE: Tile = Tile::new(&DOOR, false);
-    pub static ref ROOM_TILE: Tile = Tile::new(&ROOM, false);
-    pub static ref CURRENT_ROOM_TILE: Tile = Tile::new(&CURRENT_ROOM, false);
-    pub static ref NO_ROOM_TILE: Tile = Tile::new(&NO_ROOM, false);
+    static ref SPIKES_TILE: Tile = Tile::new(&SPIKES, false).with_damage(1);
+    static ref PIT_TILE: Tile = Tile::new(&PIT, false).with_pit();
+    static ref WATER_TILE: Tile = Tile::new(&WATER, false).with_speed(0.6);
+    static ref MUD_TILE: Tile = Tile::new(&MUD, false).with_speed(0.4);
+    static ref BRUSH_TILE: Tile = Tile::new(&BRUSH, false)
+        .with_speed(0.8)
+        .with_projectile_cover()
+        .with_hiding();
+    static ref ROCK_TILE: Tile = Tile::new(&ROCK, true).with_breakable();
+    static ref POT_TILE: Tile = Tile::new(&POT, true).with_breakable();
+    static ref CRATE_TILE: Tile = Tile::new(&CRATE, true).with_breakable();
     pub static ref CORNER_TILES: Vec<&'static Tile> =
         vec![&CORNER_TILE0, &CORNER_TILE1, &CORNER_TILE2, &CORNER_TILE3];
     pub static ref WALL_TILES: Vec<&'static Tile> = vec![&WALL_TILE0, &WALL_TILE1, &WALL_TILE2,];
@@ -37,16 +47,230 @@ lazy_static! {
         &GRASS_TILE4,
         &GRASS_TILE5
     ];
+    static ref SLAB_TILES: Vec<Tile> = SLABS.iter().map(|sprite| Tile::new(sprite, false)).collect();
+    static ref RUBBLE_TILES: Vec<Tile> =
+        RUBBLES.iter().map(|sprite| Tile::new(sprite, false)).collect();
+    static ref OUTER_CORNER_TILES: Vec<Tile> =
+        OUTER_CORNERS.iter().map(|sprite| Tile::new(sprite, true)).collect();
+    static ref EDGE_TILES: Vec<Tile> = EDGES.iter().map(|sprite| Tile::new(sprite, true)).collect();
+    static ref WALL_TOP_TILE: Tile = Tile::new(&WALL_TOP, true);
+    // Floor variants selected per level, every set has the same number of tiles
+    pub static ref FLOOR_TILESETS: Vec<Vec<&'static Tile>> = vec![
+        GRASS_TILES.clone(),
+        SLAB_TILES.iter().collect(),
+        RUBBLE_TILES.iter().collect(),
+    ];
+    static ref SANDSTONE_WALL_TILES: Vec<Tile> =
+        SANDSTONE_WALLS.iter().map(|sprite| Tile::new(sprite, true)).collect();
+    static ref SANDSTONE_EDGE_TILES: Vec<Tile> =
+        SANDSTONE_EDGES.iter().map(|sprite| Tile::new(sprite, true)).collect();
+    static ref SANDSTONE_CORNER_TILES: Vec<Tile> =
+        SANDSTONE_CORNERS.iter().map(|sprite| Tile::new(sprite, true)).collect();
+    static ref SANDSTONE_OUTER_CORNER_TILES: Vec<Tile> =
+        SANDSTONE_OUTER_CORNERS.iter().map(|sprite| Tile::new(sprite, true)).collect();
+    static ref SANDSTONE_WALL_TOP_TILE: Tile = Tile::new(&SANDSTONE_WALL_TOP, true);
+    // Wall variants selected per level like the floors, unless the room template names one
+    pub static ref WALL_TILESETS: Vec<WallTileset> = vec![
+        WallTileset {
+            name: "stone",
+            walls: WALL_TILES.clone(),
+            edges: EDGE_TILES.iter().collect(),
+            inner_corners: CORNER_TILES.clone(),
+            outer_corners: OUTER_CORNER_TILES.iter().collect(),
+            fill: &WALL_TOP_TILE,
+        },
+        WallTileset {
+            name: "sandstone",
+            walls: SANDSTONE_WALL_TILES.iter().collect(),
+            edges: SANDSTONE_EDGE_TILES.iter().collect(),
+            inner_corners: SANDSTONE_CORNER_TILES.iter().collect(),
+            outer_corners: SANDSTONE_OUTER_CORNER_TILES.iter().collect(),
+            fill: &SANDSTONE_WALL_TOP_TILE,
+        },
+    ];
+}
+
+// Tiles for every wall shape the autotiler picks. Walls are interchangeable variants drawn
+// rotated to their side, edges are ordered up, down, left and right and corners up left,
+// down left, up right and down right.
+pub struct WallTileset {
+    // Name the room templates pick the set by
+    pub name: &'static str,
+    pub walls: Vec<&'static Tile>,
+    pub edges: Vec<&'static Tile>,
+    pub inner_corners: Vec<&'static Tile>,
+    pub outer_corners: Vec<&'static Tile>,
+    pub fill: &'static Tile,
+}
+
+impl WallTileset {
+    // Index of the set called `name` in `WALL_TILESETS`
+    pub fn parse(name: &str) -> Option<usize> {
+        WALL_TILESETS.iter().position(|tileset| tileset.name == name)
+    }
+
+    pub fn tile(&self, shape: WallShape, variant: usize) -> (&'static Tile, Direction) {
+        match shape {
+            WallShape::Wall(side) => (self.walls[variant % self.walls.len()], side),
+            WallShape::Edge(side) => (self.edges[side_index(side)], Direction::UP),
+            WallShape::InnerCorner(v, h) => (self.inner_corners[corner_index(v, h)], Direction::UP),
+            WallShape::OuterCorner(v, h) => (self.outer_corners[corner_index(v, h)], Direction::UP),
+            WallShape::Fill => (self.fill, Direction::UP),
+        }
+    }
+}
+
+fn side_index(side: Direction) -> usize {
+    match side {
+        Direction::UP => 0,
+        Direction::DOWN => 1,
+        Direction::LEFT => 2,
+        _ => 3,
+    }
+}
+
+fn corner_index(vertical: Direction, horizontal: Direction) -> usize {
+    match (vertical == Direction::DOWN, horizontal == Direction::RIGHT) {
+        (false, false) => 0,
+        (true, false) => 1,
+        (false, true) => 2,
+        (true, true) => 3,
+    }
+}
+
+// Floor that does something to whoever walks on it, painted from the room templates
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum Hazard {
+    Spikes,
+    Pit,
+    Water,
+    Mud,
+    Brush,
+}
+
+impl Hazard {
+    pub fn parse(name: &str) -> Option<Hazard> {
+        match name {
+            "spikes" => Some(Hazard::Spikes),
+            "pit" => Some(Hazard::Pit),
+            "water" => Some(Hazard::Water),
+            "mud" => Some(Hazard::Mud),
+            "brush" => Some(Hazard::Brush),
+            _ => None,
+        }
+    }
+
+    pub fn tile(self) -> &'static Tile {
+        match self {
+            Hazard::Spikes => &SPIKES_TILE,
+            Hazard::Pit => &PIT_TILE,
+            Hazard::Water => &WATER_TILE,
+            Hazard::Mud => &MUD_TILE,
+            Hazard::Brush => &BRUSH_TILE,
+        }
+    }
+}
+
+// Obstacle standing on the floor until a projectile breaks it
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum Prop {
+    Rock,
+    Pot,
+    Crate,
+}
+
+impl Prop {
+    pub fn parse(name: &str) -> Option<Prop> {
+        match name {
+            "rock" => Some(Prop::Rock),
+            "pot" => Some(Prop::Pot),
+            "crate" => Some(Prop::Crate),
+            _ => None,
+        }
+    }
+
+    pub fn tile(self) -> &'static Tile {
+        match self {
+            Prop::Rock => &ROCK_TILE,
+            Prop::Pot => &POT_TILE,
+            Prop::Crate => &CRATE_TILE,
+        }
+    }
+
+    // Chance of leaving a pickup behind once broken
+    pub fn loot_chance(self) -> f64 {
+        match self {
+            Prop::Rock => 0.,
+            Prop::Pot => 0.25,
+            Prop::Crate => 0.5,
+        }
+    }
 }
 
 pub struct Tile {
     pub solid: bool,
     pub sprite: &'static Sprite,
+    // Damage dealt to the player standing on the tile
+    pub damage: u32,
+    // The player falls in and comes back out where they last stood safely
+    pub pit: bool,
+    // Multiplier of the movement speed on the tile
+    pub speed: f32,
+    // Walkable but stops projectiles like a wall
+    pub blocks_projectiles: bool,
+    // Turns back into floor when hit
+    pub breakable: bool,
+    // Enemies lose track of the player standing in it
+    pub hides: bool,
 }
 
 impl Tile {
     fn new(sprite: &'static Sprite, solid: bool) -> Tile {
-        Tile { solid, sprite }
+        Tile {
+            solid,
+            sprite,
+            damage: 0,
+            pit: false,
+            speed: 1.,
+            blocks_projectiles: solid,
+            breakable: false,
+            hides: false,
+        }
+    }
+
+    fn with_damage(mut self, damage: u32) -> Tile {
+        self.damage = damage;
+        self
+    }
+
+    fn with_pit(mut self) -> Tile {
+        self.pit = true;
+        self
+    }
+
+    fn with_speed(mut self, speed: f32) -> Tile {
+        self.speed = speed;
+        self
+    }
+
+    fn with_projectile_cover(mut self) -> Tile {
+        self.blocks_projectiles = true;
+        self
+    }
+
+    fn with_breakable(mut self) -> Tile {
+        self.breakable = true;
+        self
+    }
+
+    fn with_hiding(mut self) -> Tile {
+        self.hides = true;
+        self
+    }
+
+    // Neither hurts nor swallows whoever stands on it
+    pub fn is_safe(&self) -> bool {
+        !self.solid && !self.pit && self.damage == 0
     }
 
     pub fn render(&self, x: i32, y: i32, screen: &mut Screen, orientation: Direction) {
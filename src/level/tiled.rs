@@ -0,0 +1,449 @@
+use crate::level::template::Cell;
+use cgmath::Vector2;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// Reads room templates from maps saved by the Tiled editor in its JSON format, maps in
+// the XML based TMX format have to be exported to JSON first.
+//
+// Tiles get their cell from a `cell` string property (the names of the palette in
+// `templates.txt`) or a `solid` bool property, tiles without either are floor.
+// Later tile layers paint over earlier ones. Objects place cells on every tile they
+// cover, their type is one of `spawn`, `enemy`, `door` or `prop`. A prop is a wall
+// unless it has a `cell` property.
+
+// Tiled keeps the flip flags in the highest bits of a global tile id
+const GID_MASK: u64 = 0x1fff_ffff;
+
+struct Tileset {
+    first_gid: u64,
+    cells: HashMap<u64, Cell>,
+}
+
+struct Map {
+    dimensions: Vector2<u32>,
+    tile_size: Vector2<f64>,
+    tilesets: Vec<Tileset>,
+    cells: Vec<Cell>,
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Result<&'a Value, String> {
+    value.get(key).ok_or_else(|| format!("missing \"{}\"", key))
+}
+
+fn u64_field(value: &Value, key: &str) -> Result<u64, String> {
+    field(value, key)?
+        .as_u64()
+        .ok_or_else(|| format!("\"{}\" is not a positive integer", key))
+}
+
+fn f64_field(value: &Value, key: &str) -> Result<f64, String> {
+    match value.get(key) {
+        Some(number) => number
+            .as_f64()
+            .ok_or_else(|| format!("\"{}\" is not a number", key)),
+        None => Ok(0.),
+    }
+}
+
+fn str_field<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(Value::as_str)
+}
+
+fn read_json(path: &Path) -> Result<Value, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    serde_json::from_str(&text).map_err(|err| err.to_string())
+}
+
+// Cell given by the custom properties of a tile or object, if any
+fn property_cell(value: &Value) -> Result<Option<Cell>, String> {
+    let properties = match value.get("properties").and_then(Value::as_array) {
+        Some(properties) => properties,
+        None => return Ok(None),
+    };
+    let property = |name: &str| {
+        properties
+            .iter()
+            .find(|property| str_field(property, "name") == Some(name))
+            .and_then(|property| property.get("value"))
+    };
+    if let Some(cell) = property("cell") {
+        let name = cell.as_str().unwrap_or_default();
+        return Cell::parse(name)
+            .map(Some)
+            .ok_or_else(|| format!("unknown cell \"{}\"", name));
+    }
+    match property("solid").and_then(Value::as_bool) {
+        Some(true) => Ok(Some(Cell::Wall)),
+        Some(false) => Ok(Some(Cell::Grass)),
+        None => Ok(None),
+    }
+}
+
+impl Tileset {
+    // Tilesets are either embedded into the map or saved next to it as JSON
+    fn load(value: &Value, dir: &Path) -> Result<Tileset, String> {
+        let first_gid = u64_field(value, "firstgid")?;
+        let external;
+        let tileset = match str_field(value, "source") {
+            Some(source) => {
+                external = read_json(&dir.join(source))
+                    .map_err(|err| format!("tileset {}: {}", source, err))?;
+                &external
+            }
+            None => value,
+        };
+        let mut cells = HashMap::new();
+        if let Some(tiles) = tileset.get("tiles").and_then(Value::as_array) {
+            for tile in tiles {
+                if let Some(cell) = property_cell(tile)? {
+                    cells.insert(u64_field(tile, "id")?, cell);
+                }
+            }
+        }
+        Ok(Tileset { first_gid, cells })
+    }
+}
+
+impl Map {
+    fn cell_of(&self, gid: u64) -> Cell {
+        let gid = gid & GID_MASK;
+        if gid == 0 {
+            return Cell::Empty;
+        }
+        self.tilesets
+            .iter()
+            .filter(|tileset| tileset.first_gid <= gid)
+            .max_by_key(|tileset| tileset.first_gid)
+            .and_then(|tileset| tileset.cells.get(&(gid - tileset.first_gid)))
+            .cloned()
+            .unwrap_or(Cell::Grass)
+    }
+
+    fn set(&mut self, x: u32, y: u32, cell: Cell) {
+        let width = self.dimensions.x;
+        self.cells[(y * width + x) as usize] = cell;
+    }
+
+    fn load_layers(&mut self, layers: &Value) -> Result<(), String> {
+        let layers = layers.as_array().ok_or("\"layers\" is not an array")?;
+        for layer in layers {
+            match str_field(layer, "type") {
+                Some("tilelayer") => self.load_tile_layer(layer)?,
+                Some("objectgroup") => self.load_object_layer(layer)?,
+                Some("group") => self.load_layers(field(layer, "layers")?)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn load_tile_layer(&mut self, layer: &Value) -> Result<(), String> {
+        if str_field(layer, "encoding").unwrap_or("csv") != "csv" {
+            return Err("only CSV encoded tile layers are supported".to_owned());
+        }
+        let data = field(layer, "data")?
+            .as_array()
+            .ok_or("\"data\" is not an array")?;
+        if data.len() != self.cells.len() {
+            return Err("tile layer size differs from the map size".to_owned());
+        }
+        for (i, gid) in data.iter().enumerate() {
+            let gid = gid.as_u64().ok_or("invalid tile id")?;
+            if gid & GID_MASK != 0 {
+                self.cells[i] = self.cell_of(gid);
+            }
+        }
+        Ok(())
+    }
+
+    fn load_object_layer(&mut self, layer: &Value) -> Result<(), String> {
+        let objects = field(layer, "objects")?
+            .as_array()
+            .ok_or("\"objects\" is not an array")?;
+        for object in objects {
+            let kind = str_field(object, "type")
+                .filter(|kind| !kind.is_empty())
+                .or_else(|| str_field(object, "class"))
+                .unwrap_or_default();
+            let cell = match kind {
+                "spawn" => Cell::SpawnPoint,
+                "enemy" => Cell::EnemySpawn,
+                "door" => Cell::DoorCandidate,
+                "prop" => property_cell(object)?.unwrap_or(Cell::Wall),
+                _ => return Err(format!("unknown object type \"{}\"", kind)),
+            };
+            let (mut x, mut y) = (f64_field(object, "x")?, f64_field(object, "y")?);
+            let (width, height) = (f64_field(object, "width")?, f64_field(object, "height")?);
+            // Tile objects are anchored at their bottom left corner
+            if object.get("gid").is_some() {
+                y -= height;
+            }
+            x /= self.tile_size.x;
+            y /= self.tile_size.y;
+            let x1 = (x + width / self.tile_size.x).ceil().max(x.floor() + 1.);
+            let y1 = (y + height / self.tile_size.y).ceil().max(y.floor() + 1.);
+            if x < 0. || y < 0. || x1 > self.dimensions.x as f64 || y1 > self.dimensions.y as f64 {
+                return Err(format!("{} object outside of the map", kind));
+            }
+            for ty in y.floor() as u32..y1 as u32 {
+                for tx in x.floor() as u32..x1 as u32 {
+                    self.set(tx, ty, cell);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the Tiled JSON map at `path` into its dimensions and cells, row by row.
+pub fn load(path: &Path) -> Result<(Vector2<u32>, Vec<Cell>), String> {
+    if path.extension().and_then(|ext| ext.to_str()) == Some("tmx") {
+        return Err("TMX maps are not supported, export the map as JSON".to_owned());
+    }
+    let map = read_json(path)?;
+    parse(&map, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+// External tilesets are looked up relative to `dir`
+fn parse(map: &Value, dir: &Path) -> Result<(Vector2<u32>, Vec<Cell>), String> {
+    if str_field(map, "orientation") != Some("orthogonal") {
+        return Err("only orthogonal maps are supported".to_owned());
+    }
+    if map.get("infinite").and_then(Value::as_bool) == Some(true) {
+        return Err("infinite maps are not supported".to_owned());
+    }
+    let dimensions = Vector2::new(u64_field(map, "width")?, u64_field(map, "height")?)
+        .cast::<u32>()
+        .ok_or("map is too large")?;
+    let tile_size = Vector2::new(
+        u64_field(map, "tilewidth")? as f64,
+        u64_field(map, "tileheight")? as f64,
+    );
+    if tile_size.x == 0. || tile_size.y == 0. {
+        return Err("tiles have no size".to_owned());
+    }
+    let mut tilesets = Vec::new();
+    if let Some(values) = map.get("tilesets").and_then(Value::as_array) {
+        for value in values {
+            tilesets.push(Tileset::load(value, dir)?);
+        }
+    }
+    let mut room = Map {
+        dimensions,
+        tile_size,
+        tilesets,
+        cells: vec![Cell::Empty; (dimensions.x * dimensions.y) as usize],
+    };
+    room.load_layers(field(map, "layers")?)?;
+    Ok((dimensions, room.cells))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::tile::{Hazard, Prop};
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    const FLIPPED_HORIZONTALLY: u64 = 0x8000_0000;
+    const FLIPPED_DIAGONALLY: u64 = 0x2000_0000;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("atoma-tiled-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // A 3x2 map of 16 pixel tiles, tile 1 of the tileset is a wall
+    fn map(layers: Value) -> Value {
+        json!({
+            "orientation": "orthogonal",
+            "width": 3,
+            "height": 2,
+            "tilewidth": 16,
+            "tileheight": 16,
+            "tilesets": [{
+                "firstgid": 1,
+                "tiles": [{
+                    "id": 1,
+                    "properties": [{ "name": "solid", "type": "bool", "value": true }]
+                }]
+            }],
+            "layers": layers,
+        })
+    }
+
+    fn tile_layer(data: &[u64]) -> Value {
+        json!({ "type": "tilelayer", "data": data })
+    }
+
+    // The layers of a map with nothing but `objects`
+    fn object_layers(objects: Value) -> Value {
+        json!([{ "type": "objectgroup", "objects": objects }])
+    }
+
+    fn cells(map: &Value) -> Result<Vec<Cell>, String> {
+        parse(map, Path::new("")).map(|(_, cells)| cells)
+    }
+
+    #[test]
+    fn flip_flags_are_masked_out_of_tile_ids() {
+        let data = [
+            2 | FLIPPED_HORIZONTALLY,
+            2 | FLIPPED_DIAGONALLY,
+            1 | FLIPPED_HORIZONTALLY,
+            // Flags on an empty tile still leave it empty
+            FLIPPED_HORIZONTALLY,
+            0,
+            2,
+        ];
+        assert_eq!(
+            cells(&map(json!([tile_layer(&data)]))).unwrap(),
+            vec![
+                Cell::Wall,
+                Cell::Wall,
+                Cell::Grass,
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Wall
+            ]
+        );
+    }
+
+    #[test]
+    fn later_layers_paint_over_earlier_ones() {
+        let layers = json!([tile_layer(&[1; 6]), tile_layer(&[0, 2, 0, 0, 0, 2])]);
+        assert_eq!(
+            cells(&map(layers)).unwrap(),
+            vec![
+                Cell::Grass,
+                Cell::Wall,
+                Cell::Grass,
+                Cell::Grass,
+                Cell::Grass,
+                Cell::Wall
+            ]
+        );
+    }
+
+    #[test]
+    fn objects_place_spawn_points_doors_and_props() {
+        let mut map = map(object_layers(json!([
+            { "type": "spawn", "x": 0, "y": 0, "width": 16, "height": 16 },
+            // Tile objects are anchored at their bottom left corner
+            { "type": "door", "gid": 1, "x": 16, "y": 16, "width": 16, "height": 16 },
+            { "class": "enemy", "x": 32, "y": 0, "width": 0, "height": 0 },
+            { "type": "prop", "x": 0, "y": 16, "width": 16, "height": 16,
+              "properties": [{ "name": "cell", "type": "string", "value": "pot" }] },
+            { "type": "prop", "x": 32, "y": 16, "width": 16, "height": 16 },
+        ])));
+        map["layers"]
+            .as_array_mut()
+            .unwrap()
+            .insert(0, tile_layer(&[1; 6]));
+        assert_eq!(
+            cells(&map).unwrap(),
+            vec![
+                Cell::SpawnPoint,
+                Cell::DoorCandidate,
+                Cell::EnemySpawn,
+                Cell::Prop(Prop::Pot),
+                Cell::Grass,
+                Cell::Wall
+            ]
+        );
+    }
+
+    #[test]
+    fn objects_cover_every_tile_they_overlap() {
+        let map = map(object_layers(json!([
+            { "type": "enemy", "x": 8, "y": 8, "width": 16, "height": 16 },
+        ])));
+        assert_eq!(
+            cells(&map).unwrap(),
+            vec![
+                Cell::EnemySpawn,
+                Cell::EnemySpawn,
+                Cell::Empty,
+                Cell::EnemySpawn,
+                Cell::EnemySpawn,
+                Cell::Empty
+            ]
+        );
+    }
+
+    #[test]
+    fn external_tilesets_are_read_next_to_the_map() {
+        let dir = temp_dir("external");
+        let tileset = json!({
+            "tiles": [{
+                "id": 0,
+                "properties": [{ "name": "cell", "type": "string", "value": "water" }]
+            }]
+        });
+        fs::write(dir.join("water.json"), tileset.to_string()).unwrap();
+        let mut map = map(json!([tile_layer(&[3, 2, 1, 3, 3, 3])]));
+        map["tilesets"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({ "firstgid": 3, "source": "water.json" }));
+        fs::write(dir.join("room.json"), map.to_string()).unwrap();
+
+        let (dimensions, cells) = load(&dir.join("room.json")).unwrap();
+        assert_eq!(dimensions, Vector2::new(3, 2));
+        let water = Cell::Hazard(Hazard::Water);
+        assert_eq!(
+            cells,
+            vec![water, Cell::Wall, Cell::Grass, water, water, water]
+        );
+
+        fs::remove_file(dir.join("water.json")).unwrap();
+        assert!(load(&dir.join("room.json"))
+            .unwrap_err()
+            .starts_with("tileset water.json"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_maps_are_refused() {
+        let layers = json!([tile_layer(&[1; 6])]);
+        let broken = |change: &dyn Fn(&mut Value)| {
+            let mut map = map(layers.clone());
+            change(&mut map);
+            cells(&map).unwrap_err()
+        };
+        assert!(broken(&|map| map["orientation"] = json!("isometric")).contains("orthogonal"));
+        assert!(broken(&|map| map["infinite"] = json!(true)).contains("infinite"));
+        assert!(broken(&|map| map["width"] = json!(-3)).contains("\"width\""));
+        assert!(broken(&|map| map["tilewidth"] = json!(0)).contains("no size"));
+        assert!(broken(&|map| map["layers"] = json!({})).contains("not an array"));
+        let short = tile_layer(&[1; 5]);
+        assert!(broken(&|map| map["layers"] = json!([short])).contains("size"));
+        let base64 = json!([{ "type": "tilelayer", "encoding": "base64", "data": "AQAAAA==" }]);
+        assert!(broken(&|map| map["layers"] = base64.clone()).contains("CSV"));
+        let chest = object_layers(json!([{ "type": "chest", "x": 0, "y": 0 }]));
+        assert!(broken(&|map| map["layers"] = chest.clone()).contains("chest"));
+        let outside = object_layers(json!([{ "type": "enemy", "x": 48, "y": 0 }]));
+        assert!(broken(&|map| map["layers"] = outside.clone()).contains("outside"));
+        let unknown = json!([{
+            "id": 1,
+            "properties": [{ "name": "cell", "type": "string", "value": "lava" }]
+        }]);
+        assert!(broken(&|map| map["tilesets"][0]["tiles"] = unknown.clone()).contains("lava"));
+
+        let dir = temp_dir("malformed");
+        fs::write(dir.join("room.json"), "{ \"width\": ").unwrap();
+        assert!(load(&dir.join("room.json")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tmx_maps_are_refused() {
+        let err = load(Path::new("res/rooms/arena.tmx")).unwrap_err();
+        assert!(err.contains("TMX"), "{}", err);
+    }
+}
@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate bitflags;
+
+mod editor;
+pub mod entity;
+pub mod game;
+pub mod graphics;
+pub mod input;
+pub mod level;
+pub mod window;
@@ -1,19 +1,106 @@
-#[macro_use]
-extern crate lazy_static;
-#[macro_use]
-extern crate bitflags;
-
-mod entity;
-mod game;
-mod graphics;
-mod input;
-mod level;
+use atoma::{
+    entity, game,
+    input::replay::{InputRecorder, InputReplay},
+    level::template::TemplateRegistry,
+    window,
+};
+use std::path::PathBuf;
 
 const WIDTH: u32 = 270;
 const HEIGHT: u32 = WIDTH / 15 * 9;
 const SCALE: u32 = 6;
 
+#[derive(Default)]
+struct Options {
+    seed: Option<u64>,
+    headless_ticks: Option<u32>,
+    screenshot: Option<PathBuf>,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    templates: Option<PathBuf>,
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Options {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let value = args.next().expect("--seed requires a value");
+                    options.seed = Some(value.parse().expect("--seed must be an unsigned integer"));
+                }
+                "--headless" => {
+                    let value = args.next().expect("--headless requires a tick count");
+                    options.headless_ticks =
+                        Some(value.parse().expect("--headless must be an unsigned integer"));
+                }
+                "--screenshot" => {
+                    let value = args.next().expect("--screenshot requires a path");
+                    options.screenshot = Some(PathBuf::from(value));
+                }
+                "--record" => {
+                    let value = args.next().expect("--record requires a path");
+                    options.record = Some(PathBuf::from(value));
+                }
+                "--replay" => {
+                    let value = args.next().expect("--replay requires a path");
+                    options.replay = Some(PathBuf::from(value));
+                }
+                "--templates" => {
+                    let value = args.next().expect("--templates requires a directory");
+                    options.templates = Some(PathBuf::from(value));
+                }
+                _ => eprintln!("Ignoring unknown argument: {}", arg),
+            }
+        }
+        options
+    }
+}
+
 fn main() {
-    let mut game = game::Game::new(WIDTH, HEIGHT, SCALE);
-    game.run();
+    let options = Options::parse(std::env::args().skip(1));
+    let templates_dir = options
+        .templates
+        .clone()
+        .unwrap_or_else(TemplateRegistry::default_dir);
+    let templates = TemplateRegistry::load(&templates_dir)
+        .unwrap_or_else(|err| panic!("Error loading room templates {:?}: {}", templates_dir, err));
+    let replay = options.replay.as_ref().map(|path| {
+        InputReplay::open(path)
+            .unwrap_or_else(|err| panic!("Error loading replay {:?}: {}", path, err))
+    });
+    // A replay is only reproducible with the seed it was recorded with
+    let seed = match (&replay, options.seed) {
+        (Some(replay), Some(seed)) if replay.seed() != seed => {
+            eprintln!("Overriding the replay seed {} with {}", replay.seed(), seed);
+            seed
+        }
+        (Some(replay), None) => replay.seed(),
+        (_, seed) => seed.unwrap_or_else(rand::random),
+    };
+    let mut game = game::Game::new(WIDTH, HEIGHT, SCALE, seed, templates)
+        .unwrap_or_else(|err| panic!("Error generating level: {}", err));
+    if let Some(replay) = replay {
+        game = game.with_replay(replay);
+    }
+    if let Some(path) = options.record.as_ref() {
+        let recorder = InputRecorder::create(path, seed)
+            .unwrap_or_else(|err| panic!("Error creating recording {:?}: {:?}", path, err));
+        game = game.with_recorder(recorder);
+    }
+    match options.headless_ticks {
+        Some(ticks) => {
+            game.run_headless(ticks);
+            if let Some(pos) = game.entity_pos(entity::PLAYER_ID) {
+                println!("seed {} | player at ({}, {})", seed, pos.x, pos.y);
+            }
+            if let Some(path) = options.screenshot {
+                game.screen()
+                    .canvas()
+                    .save(&path)
+                    .unwrap_or_else(|err| panic!("Error saving screenshot {:?}: {:?}", path, err));
+            }
+        }
+        None => window::GameWindow::new(game).run(),
+    }
 }
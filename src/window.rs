@@ -0,0 +1,102 @@
+use crate::game::{Game, FRAMES_PER_SEC};
+use piston_window::generic_event::GenericEvent;
+use piston_window::{clear, image as draw_image};
+use piston_window::{AdvancedWindow, PistonWindow, WindowSettings};
+use piston_window::{Filter, G2dTexture, Texture, TextureSettings, Transformed};
+
+pub struct GameWindow {
+    game: Game,
+    window: PistonWindow,
+    texture: G2dTexture,
+}
+
+impl GameWindow {
+    pub fn new(game: Game) -> GameWindow {
+        use piston_window::OpenGL;
+
+        let scale = game.scale();
+        let dimensions = game.screen().dimensions;
+        let mut window: PistonWindow = WindowSettings::new(
+            format!("ATOMA | seed {}", game.seed()),
+            (dimensions.x * scale, dimensions.y * scale),
+        )
+        .exit_on_esc(true)
+        .opengl(OpenGL::V3_2)
+        .build()
+        .unwrap();
+
+        window.window.window.hide_cursor(true);
+
+        let texture: G2dTexture = Texture::from_image(
+            &mut window.factory,
+            game.screen().canvas(),
+            &TextureSettings::new().mag(Filter::Nearest),
+        )
+        .unwrap();
+
+        GameWindow {
+            game,
+            window,
+            texture,
+        }
+    }
+
+    pub fn run(&mut self) {
+        use std::ops::Add;
+        use std::time::{Duration, Instant};
+
+        let mut last_time = Instant::now();
+        let mut timer = Instant::now();
+        let ns = 1_000_000_000.0_f64 / FRAMES_PER_SEC as f64;
+        let mut delta = 0.0_f64;
+        let mut frames = 0_u32;
+        let mut updates = 0_u32;
+        while let Some(e) = self.window.next() {
+            if self.game.is_finished() {
+                break;
+            }
+            self.game.keyboard.borrow_mut().update(&e);
+            if !self.game.is_running() {
+                self.game.tick();
+                self.render(&e);
+                continue;
+            }
+
+            delta += last_time.elapsed().subsec_nanos() as f64 / ns;
+            last_time = Instant::now();
+            while delta >= 1.0 {
+                self.game.tick();
+                updates += 1;
+                delta -= 1.0;
+            }
+            self.render(&e);
+            frames += 1;
+
+            if (timer.elapsed().as_secs() * 1000 + timer.elapsed().subsec_millis() as u64) > 1000 {
+                timer = timer.add(Duration::from_millis(1000));
+                self.window.set_title(format!(
+                    "ATOMA | seed {} | floor {} | {} ups, {} frames",
+                    self.game.seed(),
+                    self.game.depth(),
+                    updates,
+                    frames
+                ));
+                updates = 0;
+                frames = 0;
+            }
+        }
+    }
+
+    fn render<E: GenericEvent>(&mut self, event: &E) {
+        self.game.render();
+        self.texture
+            .update(&mut self.window.encoder, self.game.screen().canvas())
+            .unwrap();
+        let texture = &self.texture;
+        let scale = self.game.scale() as f64;
+        self.window.draw_2d::<E, _, _>(event, |c, g| {
+            clear([1.0; 4], g);
+            draw_image(texture, c.scale(scale, scale).transform, g);
+        });
+    }
+}